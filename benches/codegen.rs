@@ -0,0 +1,137 @@
+//! Compares allocations of the streaming code generator with the `Vec<String>`-joining one it replaced.
+//! Run with `cargo bench --bench codegen`.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use the_super_tiny_compiler_rust::ast::NewASTNode;
+use the_super_tiny_compiler_rust::code_generator::{generate_code_with_options, CodeWriter, EmitOptions};
+use the_super_tiny_compiler_rust::parser::parser;
+use the_super_tiny_compiler_rust::tokenizer::tokenizer;
+use the_super_tiny_compiler_rust::transformer::transformer;
+
+/// Counts allocations and allocated bytes.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// `statements` top-level calls, each nested `depth` calls deep.
+fn program(statements: usize, depth: usize) -> NewASTNode {
+    let mut code = String::new();
+    for statement in 0..statements {
+        for level in 0..depth {
+            code.push_str(&format!("(add {} ", statement + level));
+        }
+        code.push_str("'leaf'");
+        code.push_str(&")".repeat(depth));
+        code.push('\n');
+    }
+
+    let new_program = transformer(parser(tokenizer(code).unwrap()).unwrap()).unwrap();
+
+    NewASTNode::NewProgram(new_program)
+}
+
+/// The generator before streaming: every node builds a `String` from its children's.
+fn generate_by_joining(new_node: &NewASTNode) -> String {
+    enum Step<'a> {
+        Enter(&'a NewASTNode),
+        Exit(&'a NewASTNode),
+    }
+
+    let mut steps: Vec<Step> = vec![Step::Enter(new_node)];
+    let mut codes: Vec<String> = vec![];
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Enter(node) => match node {
+                NewASTNode::NewProgram(new_program) => {
+                    steps.push(Step::Exit(node));
+                    steps.extend(new_program.body.iter().rev().map(Step::Enter));
+                }
+                NewASTNode::ExpressionStatement(expression_statement) => steps.push(Step::Enter(&expression_statement.expression)),
+                NewASTNode::CallExpressionWithCallee(call_expression_with_callee) => {
+                    steps.push(Step::Exit(node));
+                    steps.extend(call_expression_with_callee.arguments.iter().rev().map(Step::Enter));
+                }
+                NewASTNode::Identifier(identifier) => codes.push(format!("'{}'", identifier.name)),
+                NewASTNode::NumberLiteral(number_literal) => codes.push(format!("'{}'", number_literal.value)),
+                NewASTNode::StringLiteral(string_literal) => codes.push(format!("'{}'", string_literal.value)),
+            },
+            Step::Exit(node) => match node {
+                NewASTNode::NewProgram(new_program) => {
+                    let body = codes.split_off(codes.len() - new_program.body.len());
+                    codes.push(body.join("\n"));
+                }
+                NewASTNode::CallExpressionWithCallee(call_expression_with_callee) => {
+                    let arguments = codes.split_off(codes.len() - call_expression_with_callee.arguments.len());
+                    codes.push(format!("{}({})", call_expression_with_callee.callee.name, arguments.join(",")));
+                }
+                _ => unreachable!("only nodes with children are exited"),
+            },
+        }
+    }
+
+    codes.pop().unwrap_or_default()
+}
+
+/// Runs `f` `iterations` times and prints the allocations per run.
+fn measure(name: &str, iterations: usize, mut f: impl FnMut()) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let elapsed: Duration = start.elapsed() / iterations as u32;
+
+    println!(
+        "{:<24} {:>10} allocations {:>14} bytes {:>10.2?} per run",
+        name,
+        (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / iterations,
+        (ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated_bytes) / iterations,
+        elapsed,
+    );
+}
+
+fn main() {
+    let options = EmitOptions::default();
+    for (statements, depth) in [(10_000, 4), (100, 400)] {
+        let new_program = program(statements, depth);
+        println!("{} statements, {} calls deep:", statements, depth);
+
+        assert_eq!(generate_by_joining(&new_program), generate_code_with_options(&new_program, &options));
+
+        measure("joining strings", 10, || {
+            generate_by_joining(&new_program);
+        });
+        measure("into a String", 10, || {
+            generate_code_with_options(&new_program, &options);
+        });
+        let mut code_writer = CodeWriter::new();
+        measure("CodeWriter into io", 10, || {
+            code_writer.write(&new_program, &options, &mut io::sink()).unwrap();
+        });
+    }
+}
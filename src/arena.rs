@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::ast::{ASTNode, ASTNodeType, CallExpression, Identifier, NumberLiteral, Program, StringLiteral};
+use crate::span::Span;
+
+/// Stable identity of a node inside an `AstArena`.
+#[derive(Eq, Hash, Debug, PartialEq, Clone, Copy, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// Side table for analysis passes, e.g. `NodeMap<Type>` or `NodeMap<Span>`.
+pub type NodeMap<T> = HashMap<NodeId, T>;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ArenaNode {
+    pub(crate) node_type: ASTNodeType,
+    /// Callee name of a call expression, value of a literal, empty for the program.
+    pub(crate) value: String,
+    pub(crate) span: Span,
+    /// Span of the callee name of a call expression.
+    pub(crate) callee_span: Span,
+    pub(crate) parent: Option<NodeId>,
+    pub(crate) children: Vec<NodeId>,
+}
+
+/// Flat copy of a `Program` where every node is addressed by a `NodeId`.
+/// Ids are assigned in pre-order, so the program itself is always the first node.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AstArena {
+    nodes: Vec<ArenaNode>,
+}
+
+impl AstArena {
+    pub fn from_program(program: &Program) -> AstArena {
+        let mut arena = AstArena { nodes: vec![] };
+        let root = arena.push(ASTNodeType::Program, String::new(), program.span, None);
+
+        // (parent, child) pairs still to be added, next child last
+        let mut pending: Vec<(NodeId, &Rc<ASTNode>)> = program.body.iter().rev().map(|child| (root, child)).collect();
+
+        while let Some((parent, node)) = pending.pop() {
+            let id = match node.as_ref() {
+                ASTNode::CallExpression(call_expression) => {
+                    let id = arena.push(ASTNodeType::CallExpression, call_expression.value.clone(), call_expression.span, Some(parent));
+                    arena.nodes[id.0].callee_span = call_expression.callee_span;
+                    pending.extend(call_expression.params.iter().rev().map(|child| (id, child)));
+                    id
+                }
+                ASTNode::NumberLiteral(number_literal) => arena.push(ASTNodeType::NumberLiteral, number_literal.value.clone(), number_literal.span, Some(parent)),
+                ASTNode::StringLiteral(string_literal) => arena.push(ASTNodeType::StringLiteral, string_literal.value.clone(), string_literal.span, Some(parent)),
+                ASTNode::Identifier(identifier) => arena.push(ASTNodeType::Identifier, identifier.name.clone(), identifier.span, Some(parent)),
+                ASTNode::Program(_) | ASTNode::Root(_) => continue,
+            };
+            arena.nodes[parent.0].children.push(id);
+        }
+
+        arena
+    }
+
+    /// Rebuilds the `Rc`-based tree the rest of the pipeline works on.
+    pub fn to_program(&self) -> Program {
+        let root = self.get(self.root());
+
+        Program {
+            node_type: ASTNodeType::Program,
+            span: root.span,
+            body: self.children(self.root()).iter().map(|child| Rc::new(self.to_ast_node(*child))).collect(),
+        }
+    }
+
+    fn to_ast_node(&self, id: NodeId) -> ASTNode {
+        let node = self.get(id);
+        match node.node_type {
+            ASTNodeType::CallExpression => ASTNode::CallExpression(CallExpression {
+                node_type: node.node_type,
+                span: node.span,
+                value: node.value.clone(),
+                callee_span: node.callee_span,
+                params: node.children.iter().map(|child| Rc::new(self.to_ast_node(*child))).collect(),
+            }),
+            ASTNodeType::StringLiteral => ASTNode::StringLiteral(StringLiteral {
+                node_type: node.node_type,
+                span: node.span,
+                value: node.value.clone(),
+            }),
+            ASTNodeType::Identifier => ASTNode::Identifier(Identifier {
+                node_type: node.node_type,
+                span: node.span,
+                name: node.value.clone(),
+            }),
+            _ => ASTNode::NumberLiteral(NumberLiteral {
+                node_type: node.node_type,
+                span: node.span,
+                value: node.value.clone(),
+            }),
+        }
+    }
+
+    fn push(&mut self, node_type: ASTNodeType, value: String, span: Span, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(ArenaNode { node_type, value, span, callee_span: Span::default(), parent, children: vec![] });
+        id
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.0]
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.get(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.get(id).children
+    }
+
+    /// Parents of `id`, nearest first.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), move |ancestor| self.parent(*ancestor))
+    }
+
+    /// All ids in pre-order.
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len()).map(NodeId)
+    }
+}
+
+impl From<&Program> for AstArena {
+    fn from(program: &Program) -> Self {
+        AstArena::from_program(program)
+    }
+}
+
+#[cfg(test)]
+mod arena_tests {
+    use crate::span::Span;
+    use std::rc::Rc;
+    use crate::arena::{AstArena, NodeMap};
+    use crate::ast::{ASTNode, ASTNodeType, CallExpression, NumberLiteral, Program, StringLiteral};
+
+    fn program() -> Program {
+        Program {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                Rc::new(ASTNode::CallExpression(CallExpression {
+                    node_type: ASTNodeType::CallExpression,
+                    span: Span::default(),
+                    value: "add".to_string(),
+                    callee_span: Span::default(),
+                    params: vec![
+                        Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "2".to_string() })),
+                        Rc::new(ASTNode::CallExpression(CallExpression {
+                            node_type: ASTNodeType::CallExpression,
+                            span: Span::default(),
+                            value: "subtract".to_string(),
+                            callee_span: Span::default(),
+                            params: vec![
+                                Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "4".to_string() })),
+                                Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "2".to_string() })),
+                            ],
+                        })),
+                    ],
+                })),
+                Rc::new(ASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "hoge".to_string() })),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_arena_navigation() {
+        let arena = AstArena::from(&program());
+
+        assert_eq!(arena.len(), 7);
+
+        let root = arena.root();
+        assert_eq!(arena.get(root).node_type, ASTNodeType::Program);
+        assert_eq!(arena.parent(root), None);
+
+        let add = arena.children(root)[0];
+        assert_eq!(arena.get(add).value, "add");
+        assert_eq!(arena.parent(add), Some(root));
+
+        let subtract = arena.children(add)[1];
+        let four = arena.children(subtract)[0];
+        assert_eq!(arena.get(four).value, "4");
+        assert_eq!(arena.ancestors(four).collect::<Vec<_>>(), vec![subtract, add, root]);
+
+        let values = arena.ids().map(|id| arena.get(id).value.clone()).collect::<Vec<String>>();
+        assert_eq!(values, vec!["", "add", "2", "subtract", "4", "2", "hoge"]);
+    }
+
+    #[test]
+    fn test_arena_side_table() {
+        let arena = AstArena::from(&program());
+
+        let mut depths: NodeMap<usize> = NodeMap::new();
+        for id in arena.ids() {
+            depths.insert(id, arena.ancestors(id).count());
+        }
+
+        let subtract = arena.children(arena.children(arena.root())[0])[1];
+        assert_eq!(depths[&subtract], 2);
+        assert_eq!(depths[&arena.children(subtract)[1]], 3);
+    }
+
+    #[test]
+    fn test_arena_round_trip() {
+        let program = program();
+
+        let arena = AstArena::from(&program);
+
+        assert_eq!(arena.to_program(), program);
+    }
+}
@@ -1,8 +1,11 @@
 use std::rc::Rc;
+use crate::span::Span;
 use strum_macros::Display;
 use strum_macros::EnumIter;
 use strum_macros::EnumString;
 use strum_macros::IntoStaticStr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Eq, Hash, Debug, PartialEq, Clone, Copy, EnumString, Display, IntoStaticStr, EnumIter)]
 pub enum  ASTNodeType {
@@ -15,41 +18,64 @@ pub enum  ASTNodeType {
     Identifier,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NumberLiteral {
-    pub(crate) node_type: ASTNodeType,
-    pub(crate) value: String,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::number_literal"))]
+    pub node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub span: Span,
+    pub value: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct StringLiteral {
-    pub(crate) node_type: ASTNodeType,
-    pub(crate) value: String,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::string_literal"))]
+    pub node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub span: Span,
+    pub value: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CallExpression {
-    pub(crate) node_type: ASTNodeType,
-    pub(crate) value: String,
-    pub(crate) params: Vec<Rc<ASTNode>>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::call_expression"))]
+    pub node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub span: Span,
+    pub value: String,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub callee_span: Span,
+    pub params: Vec<Rc<ASTNode>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Root {
-    pub(crate) node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::root"))]
+    pub node_type: ASTNodeType,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Program {
-    pub(crate) node_type: ASTNodeType,
-    pub(crate) body: Vec<Rc<ASTNode>>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::program"))]
+    pub node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub span: Span,
+    pub body: Vec<Rc<ASTNode>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(tag = "type"))]
 pub enum ASTNode {
     NumberLiteral(NumberLiteral),
     StringLiteral(StringLiteral),
     CallExpression(CallExpression),
+    /// A bare name, as in macro parameter lists and templates; none are left after macro expansion.
+    Identifier(Identifier),
     Program(Program),
     Root(Root),
 }
@@ -61,13 +87,29 @@ impl ASTNode {
             ASTNode::NumberLiteral(p) => p.node_type,
             ASTNode::StringLiteral(p) => p.node_type,
             ASTNode::CallExpression(p) => p.node_type,
+            ASTNode::Identifier(p) => p.node_type,
             ASTNode::Root(p) => p.node_type,
         }
     }
+
+    pub fn get_span(&self) -> Span {
+        match self {
+            ASTNode::Program(n) => n.span,
+            ASTNode::NumberLiteral(p) => p.span,
+            ASTNode::StringLiteral(p) => p.span,
+            ASTNode::CallExpression(p) => p.span,
+            ASTNode::Identifier(p) => p.span,
+            ASTNode::Root(_) => Span::default(),
+        }
+    }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(tag = "type"))]
 pub enum NewASTNode {
+    #[cfg_attr(feature = "serde", serde(rename = "Program"))]
     NewProgram(NewProgram),
+    #[cfg_attr(feature = "serde", serde(rename = "CallExpression"))]
     CallExpressionWithCallee(CallExpressionWithCallee),
     ExpressionStatement(ExpressionStatement),
     Identifier(Identifier),
@@ -75,23 +117,82 @@ pub enum NewASTNode {
     StringLiteral(StringLiteral),
 }
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NewProgram {
-    pub(crate) node_type: ASTNodeType,
-    pub(crate) body: Vec<NewASTNode>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::program"))]
+    pub node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub span: Span,
+    pub body: Vec<NewASTNode>,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ExpressionStatement {
-    pub(crate) node_type: ASTNodeType,
-    pub(crate) expression: Box<NewASTNode>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::expression_statement"))]
+    pub node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub span: Span,
+    pub expression: Box<NewASTNode>,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CallExpressionWithCallee {
-    pub(crate) node_type: ASTNodeType,
-    pub(crate) callee: Identifier,
-    pub(crate) arguments: Vec<NewASTNode>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::call_expression"))]
+    pub node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub span: Span,
+    pub callee: Identifier,
+    pub arguments: Vec<NewASTNode>,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Identifier {
-    pub(crate) node_type: ASTNodeType,
-    pub(crate) name: String,
+    #[cfg_attr(feature = "serde", serde(skip, default = "node_type::identifier"))]
+    pub node_type: ASTNodeType,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub span: Span,
+    pub name: String,
+}
+
+impl NewASTNode {
+    pub fn get_node_type(&self) -> ASTNodeType {
+        match self {
+            NewASTNode::NewProgram(n) => n.node_type,
+            NewASTNode::CallExpressionWithCallee(n) => n.node_type,
+            NewASTNode::ExpressionStatement(n) => n.node_type,
+            NewASTNode::Identifier(n) => n.node_type,
+            NewASTNode::NumberLiteral(n) => n.node_type,
+            NewASTNode::StringLiteral(n) => n.node_type,
+        }
+    }
+
+    pub fn get_span(&self) -> Span {
+        match self {
+            NewASTNode::NewProgram(n) => n.span,
+            NewASTNode::CallExpressionWithCallee(n) => n.span,
+            NewASTNode::ExpressionStatement(n) => n.span,
+            NewASTNode::Identifier(n) => n.span,
+            NewASTNode::NumberLiteral(n) => n.span,
+            NewASTNode::StringLiteral(n) => n.span,
+        }
+    }
+}
+
+/// `node_type` is implied by the serialized `type` tag, so it is skipped
+/// and restored from these on deserialization.
+#[cfg(feature = "serde")]
+mod node_type {
+    use super::ASTNodeType;
+
+    pub fn root() -> ASTNodeType { ASTNodeType::Root }
+    pub fn program() -> ASTNodeType { ASTNodeType::Program }
+    pub fn call_expression() -> ASTNodeType { ASTNodeType::CallExpression }
+    pub fn number_literal() -> ASTNodeType { ASTNodeType::NumberLiteral }
+    pub fn string_literal() -> ASTNodeType { ASTNodeType::StringLiteral }
+    pub fn expression_statement() -> ASTNodeType { ASTNodeType::ExpressionStatement }
+    pub fn identifier() -> ASTNodeType { ASTNodeType::Identifier }
 }
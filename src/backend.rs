@@ -0,0 +1,191 @@
+use std::io::Write;
+use thiserror::Error;
+use crate::ast::NewASTNode;
+use crate::c::generate_c;
+use crate::code_generator::{CodeWriter, EmitOptions};
+use crate::python::generate_python;
+use crate::wat::generate_wat;
+
+/// Settings shared by all targets.
+#[derive(Debug, PartialEq, Clone, Default)]
+#[non_exhaustive]
+pub struct Options {
+    /// Layout of the generated code. Only `js` supports it so far; the other targets ignore it.
+    pub emit: EmitOptions,
+}
+
+/// A code generation target, selected by `name` with `--target`.
+pub trait Backend {
+    fn name(&self) -> &str;
+
+    /// Extension of the files this target generates, without the dot.
+    fn extension(&self) -> &str;
+
+    fn emit(&self, new_node: &NewASTNode, out: &mut dyn Write, options: &Options) -> anyhow::Result<()>;
+}
+
+/// The targets the CLI can choose from.
+/// Other crates build one with `Registry::with_builtin_targets()`, `register` their own
+/// backends and pass it to `cli::run_with_registry`.
+pub struct Registry {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::with_builtin_targets()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry { backends: vec![] }
+    }
+
+    /// `js`, `python`, `c` and `wat`.
+    pub fn with_builtin_targets() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Box::new(JsBackend));
+        registry.register(Box::new(PythonBackend));
+        registry.register(Box::new(CBackend));
+        registry.register(Box::new(WatBackend));
+
+        registry
+    }
+
+    /// Replaces a registered backend with the same name.
+    pub fn register(&mut self, backend: Box<dyn Backend>) {
+        self.backends.retain(|registered| registered.name() != backend.name());
+        self.backends.push(backend);
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<&dyn Backend, BackendError> {
+        self.backends.iter()
+            .find(|backend| backend.name() == name)
+            .map(|backend| backend.as_ref())
+            .ok_or_else(|| BackendError::UnknownTarget(name.to_string(), self.names().join(", ")))
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.backends.iter().map(|backend| backend.name()).collect()
+    }
+}
+
+pub struct JsBackend;
+
+impl Backend for JsBackend {
+    fn name(&self) -> &str {
+        "js"
+    }
+
+    fn extension(&self) -> &str {
+        "js"
+    }
+
+    fn emit(&self, new_node: &NewASTNode, out: &mut dyn Write, options: &Options) -> anyhow::Result<()> {
+        Ok(CodeWriter::new().write(new_node, &options.emit, out)?)
+    }
+}
+
+pub struct PythonBackend;
+
+impl Backend for PythonBackend {
+    fn name(&self) -> &str {
+        "python"
+    }
+
+    fn extension(&self) -> &str {
+        "py"
+    }
+
+    fn emit(&self, new_node: &NewASTNode, out: &mut dyn Write, _options: &Options) -> anyhow::Result<()> {
+        Ok(out.write_all(generate_python(new_node).as_bytes())?)
+    }
+}
+
+pub struct CBackend;
+
+impl Backend for CBackend {
+    fn name(&self) -> &str {
+        "c"
+    }
+
+    fn extension(&self) -> &str {
+        "c"
+    }
+
+    fn emit(&self, new_node: &NewASTNode, out: &mut dyn Write, _options: &Options) -> anyhow::Result<()> {
+        Ok(out.write_all(generate_c(new_node)?.as_bytes())?)
+    }
+}
+
+pub struct WatBackend;
+
+impl Backend for WatBackend {
+    fn name(&self) -> &str {
+        "wat"
+    }
+
+    fn extension(&self) -> &str {
+        "wat"
+    }
+
+    fn emit(&self, new_node: &NewASTNode, out: &mut dyn Write, _options: &Options) -> anyhow::Result<()> {
+        Ok(out.write_all(generate_wat(new_node)?.as_bytes())?)
+    }
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum BackendError {
+    #[error("Error: Unknown target: {0} (expected {1})")]
+    UnknownTarget(String, String),
+}
+
+#[cfg(test)]
+mod backend_tests {
+    use std::io::Write;
+    use crate::ast::{ASTNodeType, NewASTNode, NewProgram};
+    use crate::backend::{Backend, Options, Registry};
+    use crate::span::Span;
+
+    struct CountBackend;
+
+    impl Backend for CountBackend {
+        fn name(&self) -> &str {
+            "count"
+        }
+
+        fn extension(&self) -> &str {
+            "txt"
+        }
+
+        fn emit(&self, new_node: &NewASTNode, out: &mut dyn Write, _options: &Options) -> anyhow::Result<()> {
+            match new_node {
+                NewASTNode::NewProgram(new_program) => Ok(write!(out, "{} statements", new_program.body.len())?),
+                _ => Ok(write!(out, "1 statement")?),
+            }
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::with_builtin_targets();
+        registry.register(Box::new(CountBackend));
+
+        assert_eq!(registry.names(), vec!["js", "python", "c", "wat", "count"]);
+        assert_eq!(registry.get("python").unwrap().extension(), "py");
+
+        let new_program = NewASTNode::NewProgram(NewProgram { node_type: ASTNodeType::Program, span: Span::default(), body: vec![] });
+        let mut out: Vec<u8> = vec![];
+        registry.get("count").unwrap().emit(&new_program, &mut out, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "0 statements");
+    }
+
+    #[test]
+    fn test_registry_rejects_unknown_target() {
+        let result_error = Registry::with_builtin_targets().get("rust").err().unwrap();
+
+        assert_eq!(result_error.to_string(), "Error: Unknown target: rust (expected js, python, c, wat)");
+    }
+}
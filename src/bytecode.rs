@@ -0,0 +1,209 @@
+use crate::ast::{ASTNode, CallExpression, Program};
+use crate::eval::{EvalError, Value, IF};
+use crate::span::Span;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Instruction {
+    /// Pushes `constants[index]`.
+    PushConst(usize),
+    /// Pops `argc` arguments, calls the builtin named in `calls[site]` and pushes its result.
+    Call { site: usize, argc: usize },
+    Jump(usize),
+    /// Pops a bool and jumps when it is false.
+    JumpIfFalse(usize),
+    Pop,
+    /// Pops the value of a top-level expression into the results.
+    Yield,
+    /// Stops the program.
+    Ret,
+    /// Fails with `errors[index]`, where eval would fail on reaching the same node.
+    Fail(usize),
+}
+
+/// The source side of a `Call`, used for error messages.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CallSite {
+    pub(crate) name: String,
+    pub(crate) callee_span: Span,
+    pub(crate) arg_spans: Vec<Span>,
+}
+
+/// A compiled program. `spans[i]` is the source of `code[i]`:
+/// the call for `Call`, the condition for `JumpIfFalse`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Chunk {
+    pub(crate) code: Vec<Instruction>,
+    pub(crate) spans: Vec<Span>,
+    pub(crate) constants: Vec<Value>,
+    pub(crate) calls: Vec<CallSite>,
+    pub(crate) errors: Vec<EvalError>,
+}
+
+impl Chunk {
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        self.code.push(instruction);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.code.len();
+        match &mut self.code[at] {
+            Instruction::Jump(jump_target) | Instruction::JumpIfFalse(jump_target) => *jump_target = target,
+            instruction => unreachable!("not a jump: {:?}", instruction),
+        }
+    }
+}
+
+/// Compiles `program` to bytecode. Number literals are parsed and `if` arity is checked here,
+/// but failures are compiled into `Fail`, so they are reported when the VM reaches them, like eval does.
+pub fn compile(program: &Program) -> Chunk {
+    let mut chunk = Chunk::default();
+
+    for node in program.body.iter() {
+        compile_node(&mut chunk, node);
+        chunk.emit(Instruction::Yield, node.get_span());
+    }
+    chunk.emit(Instruction::Ret, program.span);
+
+    chunk
+}
+
+fn compile_node(chunk: &mut Chunk, node: &ASTNode) {
+    match node {
+        ASTNode::CallExpression(call_expression) if call_expression.value == IF => compile_if(chunk, call_expression),
+        ASTNode::CallExpression(call_expression) => {
+            for param in call_expression.params.iter() {
+                compile_node(chunk, param);
+            }
+            chunk.calls.push(CallSite {
+                name: call_expression.value.clone(),
+                callee_span: call_expression.callee_span,
+                arg_spans: call_expression.params.iter().map(|param| param.get_span()).collect(),
+            });
+            let call = Instruction::Call { site: chunk.calls.len() - 1, argc: call_expression.params.len() };
+            chunk.emit(call, call_expression.span);
+        }
+        ASTNode::NumberLiteral(number_literal) => match number_literal.value.parse() {
+            Ok(number) => push_const(chunk, Value::Number(number), number_literal.span),
+            Err(_) => fail(chunk, EvalError::InvalidNumber(number_literal.value.clone(), number_literal.span.start), number_literal.span),
+        },
+        ASTNode::StringLiteral(string_literal) => push_const(chunk, Value::String(string_literal.value.clone()), string_literal.span),
+        ASTNode::Identifier(identifier) => fail(chunk, EvalError::UnboundSymbol(identifier.name.clone(), identifier.span.start), identifier.span),
+        ASTNode::Program(program) => {
+            if program.body.is_empty() {
+                push_const(chunk, Value::Nil, program.span);
+            }
+            // Like a block: only the value of the last expression is kept
+            for (index, child) in program.body.iter().enumerate() {
+                compile_node(chunk, child);
+                if index + 1 < program.body.len() {
+                    chunk.emit(Instruction::Pop, child.get_span());
+                }
+            }
+        }
+        ASTNode::Root(_) => push_const(chunk, Value::Nil, Span::default()),
+    }
+}
+
+/// condition; JUMP_IF_FALSE else; then; JUMP end; else: else; end:
+fn compile_if(chunk: &mut Chunk, call_expression: &CallExpression) {
+    let [condition, then_branch, else_branch] = call_expression.params.as_slice() else {
+        let error = EvalError::ArityMismatch(IF.to_string(), 3, call_expression.params.len(), call_expression.span.start);
+        return fail(chunk, error, call_expression.span);
+    };
+
+    compile_node(chunk, condition);
+    let else_jump = chunk.emit(Instruction::JumpIfFalse(0), condition.get_span());
+    compile_node(chunk, then_branch);
+    let end_jump = chunk.emit(Instruction::Jump(0), call_expression.span);
+    chunk.patch_jump(else_jump);
+    compile_node(chunk, else_branch);
+    chunk.patch_jump(end_jump);
+}
+
+fn fail(chunk: &mut Chunk, error: EvalError, span: Span) {
+    chunk.errors.push(error);
+    chunk.emit(Instruction::Fail(chunk.errors.len() - 1), span);
+}
+
+fn push_const(chunk: &mut Chunk, value: Value, span: Span) {
+    let index = chunk.constants.iter().position(|constant| *constant == value).unwrap_or_else(|| {
+        chunk.constants.push(value);
+        chunk.constants.len() - 1
+    });
+    chunk.emit(Instruction::PushConst(index), span);
+}
+
+/// One instruction per line: `offset MNEMONIC operands`, with constants and callee names resolved.
+pub fn disassemble(chunk: &Chunk) -> String {
+    chunk.code.iter().enumerate()
+        .map(|(offset, instruction)| {
+            let text = match instruction {
+                Instruction::PushConst(index) => match &chunk.constants[*index] {
+                    Value::String(string) => format!("PUSH_CONST {} ; '{}'", index, string),
+                    value => format!("PUSH_CONST {} ; {}", index, value),
+                },
+                Instruction::Call { site, argc } => format!("CALL {} {}", chunk.calls[*site].name, argc),
+                Instruction::Jump(target) => format!("JUMP {:04}", target),
+                Instruction::JumpIfFalse(target) => format!("JUMP_IF_FALSE {:04}", target),
+                Instruction::Pop => "POP".to_string(),
+                Instruction::Yield => "YIELD".to_string(),
+                Instruction::Ret => "RET".to_string(),
+                Instruction::Fail(index) => format!("FAIL {} ; {}", index, chunk.errors[*index]),
+            };
+            format!("{:04} {}", offset, text)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod bytecode_tests {
+    use crate::bytecode::{compile, disassemble};
+    use crate::parser::parser;
+    use crate::tokenizer::tokenizer;
+
+    #[test]
+    fn test_compile_and_disassemble() {
+        let program = parser(tokenizer("(add 2 (subtract 4 2)) (if (lt 1 2) 'hoge' 2)".to_string()).unwrap()).unwrap();
+
+        let chunk = compile(&program);
+
+        assert_eq!(disassemble(&chunk), [
+            "0000 PUSH_CONST 0 ; 2",
+            "0001 PUSH_CONST 1 ; 4",
+            "0002 PUSH_CONST 0 ; 2",
+            "0003 CALL subtract 2",
+            "0004 CALL add 2",
+            "0005 YIELD",
+            "0006 PUSH_CONST 2 ; 1",
+            "0007 PUSH_CONST 0 ; 2",
+            "0008 CALL lt 2",
+            "0009 JUMP_IF_FALSE 0012",
+            "0010 PUSH_CONST 3 ; 'hoge'",
+            "0011 JUMP 0013",
+            "0012 PUSH_CONST 0 ; 2",
+            "0013 YIELD",
+            "0014 RET",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_compile_defers_errors_to_fail() {
+        let program = parser(tokenizer("(print 1) (if 1 2) (add 99999999999999999999 x)".to_string()).unwrap()).unwrap();
+
+        assert_eq!(disassemble(&compile(&program)), [
+            "0000 PUSH_CONST 0 ; 1",
+            "0001 CALL print 1",
+            "0002 YIELD",
+            "0003 FAIL 0 ; Error: if expects 3 arguments but got 2 at 1:11",
+            "0004 YIELD",
+            "0005 FAIL 1 ; Error: Invalid number: 99999999999999999999 at 1:25",
+            "0006 FAIL 2 ; Error: Unbound symbol: x at 1:46",
+            "0007 CALL add 2",
+            "0008 YIELD",
+            "0009 RET",
+        ].join("\n"));
+    }
+}
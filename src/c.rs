@@ -0,0 +1,223 @@
+use thiserror::Error;
+use crate::ast::NewASTNode;
+use crate::eval::IF;
+use crate::span::Position;
+
+/// Defines `value`, its constructors and a `tiny_<name>` function for each of `BUILTINS`.
+const RUNTIME: &str = include_str!("c_runtime.h");
+
+/// Builtins implemented by the C runtime, the same set the evaluator provides.
+const BUILTINS: [&str; 11] = ["add", "subtract", "multiply", "divide", "concat", "print", "eq", "lt", "le", "gt", "ge"];
+
+/// Generates a self-contained C99 translation unit: the runtime followed by a `main`
+/// running each statement in order. Builtins fail at runtime by printing to stderr and exiting with 1.
+pub fn generate_c(new_node: &NewASTNode) -> anyhow::Result<String, CError> {
+    let statements = match new_node {
+        NewASTNode::NewProgram(new_program) => new_program.body.iter().collect::<Vec<&NewASTNode>>(),
+        node => vec![node],
+    };
+
+    let mut generator = Generator { lines: vec![], indent: 1, temps: 0 };
+    for statement in statements {
+        let result = generator.expression(statement)?;
+        generator.line(format!("(void){};", result));
+    }
+
+    let mut lines: Vec<String> = vec![RUNTIME.to_string(), "int main(void) {".to_string()];
+    lines.append(&mut generator.lines);
+    lines.push("    return 0;".to_string());
+    lines.push("}".to_string());
+
+    Ok(lines.join("\n"))
+}
+
+enum Step<'a> {
+    Enter(&'a NewASTNode),
+    Call(&'a str, usize),
+    Then,
+    Else,
+    EndIf,
+}
+
+/// Every call result goes into its own `tN` variable, so arguments run left to right
+/// as in the evaluator; C leaves the order within an initializer list unspecified.
+struct Generator {
+    lines: Vec<String>,
+    indent: usize,
+    temps: usize,
+}
+
+impl Generator {
+    fn line(&mut self, line: String) {
+        self.lines.push(format!("{}{}", "    ".repeat(self.indent), line));
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("t{}", self.temps - 1)
+    }
+
+    /// Emits the statements computing `new_node` without recursion, like `code_generator`,
+    /// and returns the C expression holding its value.
+    fn expression(&mut self, new_node: &NewASTNode) -> Result<String, CError> {
+        let mut steps: Vec<Step> = vec![Step::Enter(new_node)];
+        let mut operands: Vec<String> = vec![];
+        let mut if_results: Vec<String> = vec![];
+
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(node) => match node {
+                    NewASTNode::ExpressionStatement(expression_statement) => steps.push(Step::Enter(&expression_statement.expression)),
+                    NewASTNode::CallExpressionWithCallee(call_expression) => {
+                        let name = call_expression.callee.name.as_str();
+                        let arguments = &call_expression.arguments;
+                        if name == IF {
+                            let [condition, then_branch, else_branch] = arguments.as_slice() else {
+                                return Err(CError::ArityMismatch(IF.to_string(), arguments.len(), call_expression.span.start));
+                            };
+                            steps.extend([Step::EndIf, Step::Enter(else_branch), Step::Else, Step::Enter(then_branch), Step::Then, Step::Enter(condition)]);
+                        } else if BUILTINS.contains(&name) {
+                            steps.push(Step::Call(name, arguments.len()));
+                            steps.extend(arguments.iter().rev().map(Step::Enter));
+                        } else {
+                            return Err(CError::UnknownFunction(name.to_string(), call_expression.callee.span.start));
+                        }
+                    }
+                    NewASTNode::NumberLiteral(number_literal) => {
+                        let value: i64 = number_literal.value.parse()
+                            .map_err(|_| CError::InvalidNumber(number_literal.value.clone(), number_literal.span.start))?;
+                        operands.push(number_code(value));
+                    }
+                    NewASTNode::StringLiteral(string_literal) => operands.push(format!("tiny_string({})", string_code(&string_literal.value))),
+                    NewASTNode::Identifier(identifier) => return Err(CError::UnknownFunction(identifier.name.clone(), identifier.span.start)),
+                    NewASTNode::NewProgram(new_program) => return Err(CError::NestedProgram(new_program.span.start)),
+                },
+                Step::Call(name, argc) => {
+                    let arguments = operands.split_off(operands.len() - argc);
+                    let argv = if arguments.is_empty() {
+                        "NULL".to_string()
+                    } else {
+                        format!("(value[]){{{}}}", arguments.join(", "))
+                    };
+                    let temp = self.temp();
+                    self.line(format!("value {} = tiny_{}({}, {});", temp, name, argc, argv));
+                    operands.push(temp);
+                }
+                Step::Then => {
+                    let condition = operands.pop().unwrap_or_default();
+                    let temp = self.temp();
+                    self.line(format!("value {};", temp));
+                    self.line(format!("if (tiny_truthy({})) {{", condition));
+                    self.indent += 1;
+                    if_results.push(temp);
+                }
+                Step::Else => {
+                    let value = operands.pop().unwrap_or_default();
+                    self.line(format!("{} = {};", if_results.last().unwrap_or(&String::new()), value));
+                    self.indent -= 1;
+                    self.line("} else {".to_string());
+                    self.indent += 1;
+                }
+                Step::EndIf => {
+                    let value = operands.pop().unwrap_or_default();
+                    let temp = if_results.pop().unwrap_or_default();
+                    self.line(format!("{} = {};", temp, value));
+                    self.indent -= 1;
+                    self.line("}".to_string());
+                    operands.push(temp);
+                }
+            }
+        }
+
+        Ok(operands.pop().unwrap_or_default())
+    }
+}
+
+/// `LLONG_MIN` has no literal in C, so it is written as an expression.
+fn number_code(value: i64) -> String {
+    if value == i64::MIN {
+        return "tiny_number(-9223372036854775807LL - 1)".to_string();
+    }
+
+    format!("tiny_number({}LL)", value)
+}
+
+/// A C string literal of the UTF-8 bytes of `value`. Bytes outside printable ASCII
+/// are written as octal escapes, which unlike hex escapes cannot swallow the next character.
+fn string_code(value: &str) -> String {
+    let mut code = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' => code.push_str("\\\""),
+            b'\\' => code.push_str("\\\\"),
+            b'?' => code.push_str("\\?"),
+            b'\n' => code.push_str("\\n"),
+            b' '..=b'~' => code.push(byte as char),
+            _ => code.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    code.push('"');
+
+    code
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum CError {
+    #[error("Error: Unknown function for C: {0} at {1}")]
+    UnknownFunction(String, Position),
+    #[error("Error: {0} expects 3 arguments but got {1} at {2}")]
+    ArityMismatch(String, usize, Position),
+    #[error("Error: Number does not fit in long long: {0} at {1}")]
+    InvalidNumber(String, Position),
+    #[error("Error: Cannot compile a nested program to C at {0}")]
+    NestedProgram(Position),
+}
+
+#[cfg(test)]
+mod c_tests {
+    use crate::c::{generate_c, number_code, string_code};
+    use crate::test_support::new_ast::{call, number, program, statement, string};
+
+    #[test]
+    fn test_generate_c_main() {
+        let new_program = program(vec![
+            statement(call("print", vec![call("add", vec![number("2"), number("3")]), string("hoge")])),
+            statement(call("if", vec![call("lt", vec![number("1"), number("2")]), call("print", vec![]), number("0")])),
+        ]);
+
+        let code = generate_c(&new_program).unwrap();
+
+        assert!(code.starts_with("/* Runtime for C generated"));
+        assert!(code.ends_with(&[
+            "int main(void) {",
+            "    value t0 = tiny_add(2, (value[]){tiny_number(2LL), tiny_number(3LL)});",
+            "    value t1 = tiny_print(2, (value[]){t0, tiny_string(\"hoge\")});",
+            "    (void)t1;",
+            "    value t2 = tiny_lt(2, (value[]){tiny_number(1LL), tiny_number(2LL)});",
+            "    value t3;",
+            "    if (tiny_truthy(t2)) {",
+            "        value t4 = tiny_print(0, NULL);",
+            "        t3 = t4;",
+            "    } else {",
+            "        t3 = tiny_number(0LL);",
+            "    }",
+            "    (void)t3;",
+            "    return 0;",
+            "}",
+        ].join("\n")));
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(string_code("say \"hi\"\\??=\n"), "\"say \\\"hi\\\"\\\\\\?\\?=\\n\"");
+        assert_eq!(string_code("é1"), "\"\\303\\2511\"");
+        assert_eq!(number_code(i64::MIN), "tiny_number(-9223372036854775807LL - 1)");
+    }
+
+    #[test]
+    fn test_generate_c_rejects_unknown_function() {
+        let result_error = generate_c(&program(vec![statement(call("fullName", vec![string("hoge")]))])).unwrap_err();
+
+        assert_eq!(result_error.to_string(), "Error: Unknown function for C: fullName at 0:1");
+    }
+}
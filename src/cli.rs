@@ -0,0 +1,532 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+use thiserror::Error;
+use crate::ast::{ASTNodeType, NewASTNode, NewProgram, Program};
+use crate::backend::{Options as BackendOptions, Registry};
+use crate::bytecode::{compile, disassemble};
+use crate::code_generator::{generate_code_with_source_map, EmitMode, EmitOptions};
+use crate::dot::{new_to_dot, to_dot};
+use crate::eval::{eval, Environment};
+use crate::expander::to_source;
+use crate::module::{bundle, load_program};
+use crate::optimizer::{optimize, OptLevel};
+use crate::repl::DEFAULT_HISTORY_FILE;
+use crate::parser::parser;
+use crate::token::Token;
+use crate::tokenizer::tokenizer;
+use crate::types::{check, check_with_functions};
+use crate::vm;
+use crate::functions::FunctionRegistry;
+use crate::transformer::transformer_with_functions;
+use crate::traverser::{NewVisitors, Visitors};
+use crate::visitor::{NewVisitor, Visitor};
+
+/// Output of each compiler pass, in pipeline order.
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy, EnumString, Display)]
+pub enum Stage {
+    #[strum(serialize = "source")]
+    Source,
+    #[strum(serialize = "tokens")]
+    Tokens,
+    #[strum(serialize = "ast")]
+    Ast,
+    /// The AST after macro expansion, printed as code.
+    #[strum(serialize = "expanded")]
+    Expanded,
+    #[strum(serialize = "dot-ast")]
+    DotAst,
+    #[strum(serialize = "eval")]
+    Eval,
+    #[strum(serialize = "bytecode")]
+    Bytecode,
+    #[strum(serialize = "vm")]
+    Vm,
+    #[strum(serialize = "types")]
+    Types,
+    #[strum(serialize = "new-ast")]
+    NewAst,
+    #[strum(serialize = "estree")]
+    Estree,
+    #[strum(serialize = "dot-new-ast")]
+    DotNewAst,
+    /// Generated code for `--target`; `js` is kept as an alias from when it was the only target.
+    #[strum(to_string = "code", serialize = "js")]
+    Code,
+    /// Source Map v3 of the `js` target's code.
+    #[strum(serialize = "source-map")]
+    SourceMap,
+}
+
+impl Stage {
+    /// The pipeline stage whose output is printed for this `--emit` value.
+    fn pipeline_stage(self) -> Stage {
+        match self {
+            Stage::DotAst => Stage::Ast,
+            Stage::Eval | Stage::Bytecode | Stage::Vm | Stage::Types => Stage::Expanded,
+            Stage::Estree | Stage::DotNewAst | Stage::SourceMap => Stage::NewAst,
+            stage => stage,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliOptions {
+    /// Stage the input is in; every stage but `source` is read as JSON.
+    pub from: Stage,
+    /// Stage to stop at and print; every stage but `expanded`, `code` and the `dot-*` graphs is written as JSON.
+    /// `source-map` maps the `js` code back to `input`, and embeds the source when it was not JSON.
+    /// `eval` and `vm` run the program instead and print what it prints; `bytecode` is a disassembly.
+    /// `types` prints the inferred type of each top-level expression, one per line.
+    /// `expanded` prints the program after macro expansion as code.
+    pub emit: Stage,
+    /// Name of the backend generating the `code` stage, set with `--target`.
+    pub target: String,
+    /// Layout of the generated code; `--format` sets its mode.
+    pub emit_options: EmitOptions,
+    /// Rejects calls to functions that are not builtins, set with `--strict`.
+    pub strict: bool,
+    /// Optimization applied between the `ast` and `new-ast` stages, set with `-O<level>`.
+    pub opt_level: OptLevel,
+    /// Input file; stdin when omitted. Its `import`s are resolved relative to its directory, or the current one for stdin.
+    pub input: Option<String>,
+    /// Writes the code of each module into this directory instead of printing them bundled, set with `--out-dir`.
+    pub out_dir: Option<PathBuf>,
+    /// Node types filled in the `dot-ast` and `dot-new-ast` graphs, one per `--highlight`.
+    pub highlight: Vec<ASTNodeType>,
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<CliOptions> {
+    let mut options = CliOptions {
+        from: Stage::Source,
+        emit: Stage::Code,
+        target: "js".to_string(),
+        emit_options: EmitOptions::default(),
+        strict: false,
+        opt_level: OptLevel::O0,
+        input: None,
+        out_dir: None,
+        highlight: vec![],
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--emit" | "--from" => {
+                let value = args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?;
+                let stage = Stage::from_str(&value).map_err(|_| CliError::UnknownStage(value.clone()))?;
+                if arg == "--emit" {
+                    options.emit = stage;
+                } else {
+                    options.from = stage;
+                }
+            }
+            "--target" => options.target = args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?,
+            "--format" => {
+                let value = args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?;
+                options.emit_options.mode = EmitMode::from_str(&value).map_err(|_| CliError::UnknownFormat(value.clone()))?;
+            }
+            "--out-dir" => options.out_dir = Some(PathBuf::from(args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?)),
+            "--highlight" => {
+                let value = args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?;
+                options.highlight.push(ASTNodeType::from_str(&value).map_err(|_| CliError::UnknownNodeType(value.clone()))?);
+            }
+            "--strict" => options.strict = true,
+            "-O" => options.opt_level = OptLevel::O1,
+            _ if arg.starts_with("-O") => {
+                options.opt_level = OptLevel::from_str(&arg[2..]).map_err(|_| CliError::UnknownOptLevel(arg.clone()))?;
+            }
+            _ if arg.starts_with("--") => return Err(CliError::UnknownOption(arg).into()),
+            _ => options.input = Some(arg),
+        }
+    }
+
+    if options.from != options.from.pipeline_stage() || options.from > Stage::NewAst || options.from == Stage::Expanded {
+        return Err(CliError::UnreadableStage(options.from).into());
+    }
+    if options.from > options.emit.pipeline_stage() || options.from == options.emit {
+        return Err(CliError::StageOrder(options.from, options.emit).into());
+    }
+    if options.out_dir.is_some() && (options.input.is_none() || options.from > Stage::Ast || options.emit != Stage::Code) {
+        return Err(CliError::InvalidOutDir.into());
+    }
+
+    Ok(options)
+}
+
+/// Arguments of the `repl` subcommand: `[--history <path>]`. Returns the history file.
+pub fn parse_repl_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<PathBuf> {
+    let mut history = PathBuf::from(DEFAULT_HISTORY_FILE);
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--history" => history = PathBuf::from(args.next().ok_or_else(|| CliError::MissingValue(arg.clone()))?),
+            _ => return Err(CliError::UnknownOption(arg).into()),
+        }
+    }
+
+    Ok(history)
+}
+
+enum Artifact {
+    Source(String),
+    Tokens(Vec<Token>),
+    Ast(Program),
+    Expanded(Program),
+    NewAst(NewProgram),
+    Code(String),
+}
+
+impl Artifact {
+    fn stage(&self) -> Stage {
+        match self {
+            Artifact::Source(_) => Stage::Source,
+            Artifact::Tokens(_) => Stage::Tokens,
+            Artifact::Ast(_) => Stage::Ast,
+            Artifact::Expanded(_) => Stage::Expanded,
+            Artifact::NewAst(_) => Stage::NewAst,
+            Artifact::Code(_) => Stage::Code,
+        }
+    }
+
+    /// Runs the pass that produces the next stage.
+    fn advance(self, options: &CliOptions, registry: &Registry) -> anyhow::Result<Artifact> {
+        let artifact = match self {
+            Artifact::Source(code) => Artifact::Tokens(tokenizer(code)?),
+            Artifact::Tokens(tokens) => Artifact::Ast(parser(tokens)?),
+            Artifact::Ast(program) => {
+                let modules = load_program(&entry_path(options), program)?;
+                for warning in modules.iter().flat_map(|module| &module.warnings) {
+                    eprintln!("{}", warning);
+                }
+                Artifact::Expanded(bundle(modules))
+            }
+            Artifact::Expanded(program) => {
+                let mut functions = FunctionRegistry::with_builtins();
+                functions.strict = options.strict;
+                // Only code generation is stopped by type errors, so the trees of a mistyped program can still be inspected.
+                if options.emit == Stage::Code {
+                    check_with_functions(&program, &functions)?;
+                }
+                Artifact::NewAst(transformer_with_functions(optimize(program, options.opt_level), &functions, &options.target)?)
+            }
+            Artifact::NewAst(new_program) => {
+                let mut out: Vec<u8> = vec![];
+                let backend_options = BackendOptions { emit: options.emit_options, ..BackendOptions::default() };
+                registry.get(&options.target)?.emit(&NewASTNode::NewProgram(new_program), &mut out, &backend_options)?;
+                Artifact::Code(String::from_utf8(out)?)
+            }
+            Artifact::Code(code) => Artifact::Code(code),
+        };
+
+        Ok(artifact)
+    }
+}
+
+pub fn run(options: &CliOptions, input: String) -> anyhow::Result<String> {
+    run_with_registry(options, input, &Registry::with_builtin_targets())
+}
+
+/// Like `run`, with `--target` looked up in `registry`.
+pub fn run_with_registry(options: &CliOptions, input: String, registry: &Registry) -> anyhow::Result<String> {
+    if options.emit == Stage::Code {
+        registry.get(&options.target)?;
+    }
+
+    let source = (options.from == Stage::Source).then(|| input.clone());
+
+    let mut artifact = read_artifact(options.from, input)?;
+    if let Some(out_dir) = &options.out_dir {
+        while artifact.stage() < Stage::Ast {
+            artifact = artifact.advance(options, registry)?;
+        }
+        let Artifact::Ast(program) = artifact else {
+            unreachable!("parse_args only accepts --out-dir with inputs up to the ast stage");
+        };
+        return write_modules(program, out_dir, options, registry);
+    }
+    while artifact.stage() < options.emit.pipeline_stage() {
+        artifact = artifact.advance(options, registry)?;
+    }
+
+    write_artifact(artifact, options, source.as_deref())
+}
+
+/// `options.input`, or a file in the current directory for stdin, to resolve imports from.
+fn entry_path(options: &CliOptions) -> PathBuf {
+    PathBuf::from(options.input.as_deref().unwrap_or("<stdin>"))
+}
+
+/// Writes the code of each module of `program` into `out_dir`, at its path relative to the input file.
+/// Returns the files written, one per line, each after those of the modules it imports.
+fn write_modules(program: Program, out_dir: &Path, options: &CliOptions, registry: &Registry) -> anyhow::Result<String> {
+    let extension = registry.get(&options.target)?.extension();
+
+    let mut written = vec![];
+    for module in load_program(&entry_path(options), program)? {
+        for warning in &module.warnings {
+            eprintln!("{}", warning);
+        }
+        let mut artifact = Artifact::Expanded(module.program);
+        while artifact.stage() < Stage::Code {
+            artifact = artifact.advance(options, registry)?;
+        }
+        let Artifact::Code(mut code) = artifact else {
+            unreachable!("the pipeline ends with code");
+        };
+        if !code.ends_with('\n') {
+            code.push('\n');
+        }
+
+        let path = out_dir.join(&module.name).with_extension(extension);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, code)?;
+        written.push(path.display().to_string());
+    }
+
+    Ok(written.join("\n"))
+}
+
+#[cfg(feature = "serde")]
+fn read_artifact(stage: Stage, input: String) -> anyhow::Result<Artifact> {
+    use crate::json::{new_program_from_json, program_from_json, tokens_from_json};
+
+    let artifact = match stage {
+        Stage::Source => Artifact::Source(input),
+        Stage::Tokens => Artifact::Tokens(tokens_from_json(&input)?),
+        Stage::Ast => Artifact::Ast(program_from_json(&input)?),
+        Stage::NewAst => Artifact::NewAst(new_program_from_json(&input)?),
+        Stage::Expanded | Stage::DotAst | Stage::Eval | Stage::Bytecode | Stage::Vm | Stage::Types | Stage::Estree | Stage::DotNewAst | Stage::Code | Stage::SourceMap => return Err(CliError::UnreadableStage(stage).into()),
+    };
+
+    Ok(artifact)
+}
+
+#[cfg(not(feature = "serde"))]
+fn read_artifact(stage: Stage, input: String) -> anyhow::Result<Artifact> {
+    match stage {
+        Stage::Source => Ok(Artifact::Source(input)),
+        stage => Err(CliError::JsonUnsupported(stage).into()),
+    }
+}
+
+/// Returns what the program printed, without the final newline.
+/// `emit` picks the evaluator or the bytecode VM.
+fn run_program(program: &Program, emit: Stage) -> anyhow::Result<String> {
+    let mut out: Vec<u8> = vec![];
+    if emit == Stage::Vm {
+        vm::run(&compile(program), &Environment::new(), &mut out)?;
+    } else {
+        eval(program, &Environment::new(), &mut out)?;
+    }
+
+    let mut output = String::from_utf8(out)?;
+    if output.ends_with('\n') {
+        output.pop();
+    }
+
+    Ok(output)
+}
+
+/// Maps the `js` code generated from `new_program` to `options.input`, or `<stdin>`.
+fn source_map(new_program: NewProgram, options: &CliOptions, source: Option<&str>) -> String {
+    let input = options.input.as_deref().unwrap_or("<stdin>");
+    let (_, source_map) = generate_code_with_source_map(&NewASTNode::NewProgram(new_program), &options.emit_options, input, source);
+
+    source_map.to_json()
+}
+
+/// Visits nothing; registering it for a node type is what makes `to_dot` and `new_to_dot` fill those nodes.
+struct Highlight;
+
+impl Visitor for Highlight {}
+
+impl NewVisitor for Highlight {}
+
+fn highlight_visitors(options: &CliOptions) -> Visitors {
+    options.highlight.iter().map(|node_type| (*node_type, Box::new(Highlight) as Box<dyn Visitor>)).collect()
+}
+
+fn new_highlight_visitors(options: &CliOptions) -> NewVisitors {
+    options.highlight.iter().map(|node_type| (*node_type, Box::new(Highlight) as Box<dyn NewVisitor>)).collect()
+}
+
+/// `source` is the original code when the input was not JSON.
+#[cfg(feature = "serde")]
+fn write_artifact(artifact: Artifact, options: &CliOptions, source: Option<&str>) -> anyhow::Result<String> {
+    use crate::ast::ASTNode;
+    use crate::estree::to_estree;
+    use crate::json::to_json;
+
+    let emit = options.emit;
+    match artifact {
+        Artifact::Ast(program) if emit == Stage::DotAst => Ok(to_dot(&program, Some(&highlight_visitors(options)))),
+        Artifact::Expanded(program) if emit == Stage::Eval || emit == Stage::Vm => run_program(&program, emit),
+        Artifact::Expanded(program) if emit == Stage::Bytecode => Ok(disassemble(&compile(&program))),
+        Artifact::Expanded(program) if emit == Stage::Types => Ok(check(&program)?.iter().map(|t| t.to_string()).collect::<Vec<String>>().join("\n")),
+        Artifact::Expanded(program) => Ok(to_source(&program)),
+        Artifact::NewAst(new_program) if emit == Stage::DotNewAst => Ok(new_to_dot(&new_program, Some(&new_highlight_visitors(options)))),
+        Artifact::NewAst(new_program) if emit == Stage::SourceMap => Ok(source_map(new_program, options, source)),
+        Artifact::Source(code) => Ok(code),
+        Artifact::Tokens(tokens) => to_json(&tokens),
+        Artifact::Ast(program) => to_json(&ASTNode::Program(program)),
+        Artifact::NewAst(new_program) if emit == Stage::Estree => to_json(&to_estree(&new_program, source)),
+        Artifact::NewAst(new_program) => to_json(&NewASTNode::NewProgram(new_program)),
+        Artifact::Code(code) => Ok(code),
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_artifact(artifact: Artifact, options: &CliOptions, source: Option<&str>) -> anyhow::Result<String> {
+    let emit = options.emit;
+    match artifact {
+        Artifact::Ast(program) if emit == Stage::DotAst => Ok(to_dot(&program, Some(&highlight_visitors(options)))),
+        Artifact::Expanded(program) if emit == Stage::Eval || emit == Stage::Vm => run_program(&program, emit),
+        Artifact::Expanded(program) if emit == Stage::Bytecode => Ok(disassemble(&compile(&program))),
+        Artifact::Expanded(program) if emit == Stage::Types => Ok(check(&program)?.iter().map(|t| t.to_string()).collect::<Vec<String>>().join("\n")),
+        Artifact::Expanded(program) => Ok(to_source(&program)),
+        Artifact::NewAst(new_program) if emit == Stage::DotNewAst => Ok(new_to_dot(&new_program, Some(&new_highlight_visitors(options)))),
+        Artifact::NewAst(new_program) if emit == Stage::SourceMap => Ok(source_map(new_program, options, source)),
+        Artifact::Code(code) => Ok(code),
+        artifact => Err(CliError::JsonUnsupported(artifact.stage()).into()),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("Error: Missing value for option: {0}")]
+    MissingValue(String),
+    #[error("Error: Unknown option: {0}")]
+    UnknownOption(String),
+    #[error("Error: Unknown stage: {0} (expected source, tokens, ast, expanded, dot-ast, eval, bytecode, vm, types, new-ast, estree, dot-new-ast, code or source-map)")]
+    UnknownStage(String),
+    #[error("Error: Unknown format: {0} (expected compact, pretty or minify)")]
+    UnknownFormat(String),
+    #[error("Error: Unknown optimization level: {0} (expected -O0 or -O1)")]
+    UnknownOptLevel(String),
+    #[error("Error: Cannot emit {1} from {0}")]
+    StageOrder(Stage, Stage),
+    #[error("Error: Cannot read input as {0}")]
+    UnreadableStage(Stage),
+    #[error("Error: --out-dir needs an input file of source, tokens or ast, and --emit code")]
+    InvalidOutDir,
+    #[error("Error: Unknown node type: {0} (expected Program, CallExpression, NumberLiteral, StringLiteral, ExpressionStatement or Identifier)")]
+    UnknownNodeType(String),
+    #[cfg(not(feature = "serde"))]
+    #[error("Error: Reading or writing {0} as JSON requires the `serde` feature")]
+    JsonUnsupported(Stage),
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use crate::ast::ASTNodeType;
+    use crate::cli::{parse_args, run, CliOptions, Stage};
+    use crate::code_generator::{EmitMode, EmitOptions};
+    use crate::optimizer::OptLevel;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(args(&["--from", "tokens", "--emit", "new-ast", "input.lisp"])).unwrap();
+
+        assert_eq!(options, CliOptions { from: Stage::Tokens, emit: Stage::NewAst, target: "js".to_string(), emit_options: EmitOptions::default(), strict: false, opt_level: OptLevel::O0, input: Some("input.lisp".to_string()), out_dir: None, highlight: vec![] });
+    }
+
+    #[test]
+    fn test_parse_args_defaults() {
+        let options = parse_args(args(&[])).unwrap();
+
+        assert_eq!(options, CliOptions { from: Stage::Source, emit: Stage::Code, target: "js".to_string(), emit_options: EmitOptions::default(), strict: false, opt_level: OptLevel::O0, input: None, out_dir: None, highlight: vec![] });
+    }
+
+    #[test]
+    fn test_parse_args_rejects_backwards_stages() {
+        let result_error = parse_args(args(&["--from", "ast", "--emit", "tokens"])).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Cannot emit tokens from ast");
+    }
+
+    #[test]
+    fn test_parse_args_rejects_unknown_stage() {
+        let result_error = parse_args(args(&["--emit", "wasm"])).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Unknown stage: wasm (expected source, tokens, ast, expanded, dot-ast, eval, bytecode, vm, types, new-ast, estree, dot-new-ast, code or source-map)");
+    }
+
+    #[test]
+    fn test_parse_args_rejects_reading_dot() {
+        let result_error = parse_args(args(&["--from", "dot-ast"])).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Cannot read input as dot-ast");
+    }
+
+    #[test]
+    fn test_parse_args_rejects_reading_expanded() {
+        let result_error = parse_args(args(&["--from", "expanded"])).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Cannot read input as expanded");
+    }
+
+    #[test]
+    fn test_parse_args_opt_level() {
+        assert_eq!(parse_args(args(&["-O"])).unwrap().opt_level, OptLevel::O1);
+        assert_eq!(parse_args(args(&["-O1", "-O0"])).unwrap().opt_level, OptLevel::O0);
+
+        let result_error = parse_args(args(&["-O3"])).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Unknown optimization level: -O3 (expected -O0 or -O1)");
+    }
+
+    #[test]
+    fn test_parse_args_format() {
+        assert_eq!(parse_args(args(&["--format", "minify"])).unwrap().emit_options.mode, EmitMode::Minify);
+
+        let result_error = parse_args(args(&["--format", "tidy"])).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Unknown format: tidy (expected compact, pretty or minify)");
+    }
+
+    #[test]
+    fn test_parse_args_strict() {
+        assert!(parse_args(args(&["--strict"])).unwrap().strict);
+    }
+
+    #[test]
+    fn test_parse_args_out_dir() {
+        assert_eq!(parse_args(args(&["--out-dir", "out", "main.lisp"])).unwrap().out_dir, Some("out".into()));
+
+        let result_error = parse_args(args(&["--out-dir", "out"])).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: --out-dir needs an input file of source, tokens or ast, and --emit code");
+    }
+
+    #[test]
+    fn test_parse_args_highlight() {
+        let options = parse_args(args(&["--emit", "dot-ast", "--highlight", "CallExpression", "--highlight", "Identifier"])).unwrap();
+
+        assert_eq!(options.highlight, vec![ASTNodeType::CallExpression, ASTNodeType::Identifier]);
+
+        let result_error = parse_args(args(&["--highlight", "Call"])).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Unknown node type: Call (expected Program, CallExpression, NumberLiteral, StringLiteral, ExpressionStatement or Identifier)");
+    }
+
+    #[test]
+    fn test_parse_args_target() {
+        assert_eq!(parse_args(args(&["--target", "python"])).unwrap().target, "python");
+    }
+
+    #[test]
+    fn test_run_rejects_unknown_target() {
+        let options = parse_args(args(&["--target", "rust"])).unwrap();
+        let result_error = run(&options, "(add 1 2)".to_string()).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Unknown target: rust (expected js, python, c, wat)");
+    }
+}
@@ -1,34 +1,502 @@
-use crate::ast::{NewASTNode, NewProgram};
+use std::fmt;
+use std::io;
+use strum_macros::{Display, EnumString};
+use crate::ast::NewASTNode;
+use crate::eval::IF;
+use crate::source_map::{Mapping, SourceMap};
+use crate::span::Span;
+
+/// How generated code is laid out.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, EnumString, Display)]
+pub enum EmitMode {
+    /// One statement per line and no spaces, as `generate_code` has always written.
+    #[default]
+    #[strum(serialize = "compact")]
+    Compact,
+    /// Statements end with `;` and are separated by blank lines;
+    /// calls that do not fit in `line_width` put each argument on its own line.
+    #[strum(serialize = "pretty")]
+    Pretty,
+    /// Everything on one line, statements separated by `;`.
+    #[strum(serialize = "minify")]
+    Minify,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EmitOptions {
+    pub mode: EmitMode,
+    /// Spaces per nesting level in `pretty` mode.
+    pub indent: usize,
+    /// Column `pretty` mode wraps arguments at. Lines still run over when one name or literal is too long.
+    pub line_width: usize,
+}
+
+impl Default for EmitOptions {
+    fn default() -> EmitOptions {
+        EmitOptions { mode: EmitMode::Compact, indent: 2, line_width: 80 }
+    }
+}
 
 pub fn generate_code(new_node: NewASTNode) -> String {
-    generate(&new_node)
+    generate_code_with_options(&new_node, &EmitOptions::default())
+}
+
+pub fn generate_code_with_options(new_node: &NewASTNode, options: &EmitOptions) -> String {
+    let mut code = String::new();
+    generate(new_node, options, &mut Output::new(&mut code, false)).expect("writing to a String cannot fail");
+
+    code
+}
+
+/// Generates code along with a source map pointing back to `source`, the file the nodes were read from.
+/// Calls, identifiers and literals are mapped; nodes without a span are not.
+pub fn generate_code_with_source_map(new_node: &NewASTNode, options: &EmitOptions, source: &str, source_content: Option<&str>) -> (String, SourceMap) {
+    let mut code = String::new();
+    let mut output = Output::new(&mut code, true);
+    generate(new_node, options, &mut output).expect("writing to a String cannot fail");
+    let mappings = output.mappings.unwrap_or_default();
+
+    (code, SourceMap::new(source, source_content, &mappings))
+}
+
+/// Streams the code into `out` as it is generated, without building it in memory first.
+pub fn write_code(new_node: &NewASTNode, options: &EmitOptions, out: &mut dyn fmt::Write) -> fmt::Result {
+    generate(new_node, options, &mut Output::new(out, false))
+}
+
+/// How much `CodeWriter` buffers before writing to its `io::Write`.
+const BUFFER_SIZE: usize = 8 * 1024;
+
+/// Streams code into an `io::Write` through a buffer that is kept between calls,
+/// so writing many programs allocates it once.
+#[derive(Default)]
+pub struct CodeWriter {
+    buffer: String,
+}
+
+impl CodeWriter {
+    pub fn new() -> CodeWriter {
+        CodeWriter { buffer: String::with_capacity(BUFFER_SIZE) }
+    }
+
+    pub fn write(&mut self, new_node: &NewASTNode, options: &EmitOptions, out: &mut dyn io::Write) -> io::Result<()> {
+        self.buffer.clear();
+        let mut sink = IoSink { buffer: &mut self.buffer, out, error: None };
+
+        let result = write_code(new_node, options, &mut sink).and_then(|_| sink.flush());
+        match (result, sink.error) {
+            (Ok(()), _) => Ok(()),
+            (Err(_), Some(error)) => Err(error),
+            (Err(_), None) => Err(io::Error::new(io::ErrorKind::Other, "failed to generate code")),
+        }
+    }
+}
+
+/// Adapts an `io::Write` to `fmt::Write`, keeping the `io::Error` that `fmt::Error` cannot carry.
+struct IoSink<'a> {
+    buffer: &'a mut String,
+    out: &'a mut dyn io::Write,
+    error: Option<io::Error>,
+}
+
+impl IoSink<'_> {
+    fn flush(&mut self) -> fmt::Result {
+        if let Err(error) = self.out.write_all(self.buffer.as_bytes()) {
+            self.error = Some(error);
+            return Err(fmt::Error);
+        }
+        self.buffer.clear();
+
+        Ok(())
+    }
+}
+
+impl fmt::Write for IoSink<'_> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.buffer.push_str(text);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+enum Step<'a> {
+    /// `suffix` is the width of the text that follows the node on its line,
+    /// which `pretty` mode keeps within `line_width` too.
+    Enter { node: &'a NewASTNode, suffix: usize },
+    Text(&'static str),
+    /// A line break followed by the current indentation.
+    Newline,
+    Indent,
+    Dedent,
+    /// Ends a call laid out on one line in `pretty` mode.
+    EndFlat,
+}
+
+/// Where generated code goes, and the position it has reached.
+struct Output<'a> {
+    out: &'a mut dyn fmt::Write,
+    line: usize,
+    column: usize,
+    indent: usize,
+    /// Only collected for source maps.
+    mappings: Option<Vec<Mapping>>,
+}
+
+impl Output<'_> {
+    fn new(out: &mut dyn fmt::Write, with_mappings: bool) -> Output<'_> {
+        Output { out, line: 0, column: 0, indent: 0, mappings: with_mappings.then(Vec::new) }
+    }
+
+    fn write(&mut self, text: &str) -> fmt::Result {
+        for ch in text.chars() {
+            if ch == '\n' {
+                self.line += 1;
+                self.column = 0;
+            } else {
+                self.column += 1;
+            }
+        }
+
+        self.out.write_str(text)
+    }
+
+    /// A quoted literal, the way every identifier and literal is written.
+    fn write_quoted(&mut self, text: &str) -> fmt::Result {
+        self.write("'")?;
+        self.write(text)?;
+        self.write("'")
+    }
+
+    fn write_indentation(&mut self, width: usize) -> fmt::Result {
+        const SPACES: &str = "                                ";
+        let mut remaining = width;
+        while remaining > 0 {
+            let chunk = remaining.min(SPACES.len());
+            self.write(&SPACES[..chunk])?;
+            remaining -= chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Maps what is written next to `span`.
+    fn map(&mut self, span: Span, name: Option<&str>) {
+        let Some(mappings) = &mut self.mappings else {
+            return;
+        };
+        if span.is_empty() {
+            return;
+        }
+
+        mappings.push(Mapping {
+            generated_line: self.line,
+            generated_column: self.column,
+            source: span.start,
+            name: name.map(|name| name.to_string()),
+        });
+    }
+}
+
+/// Generates code without recursion: a node writes what comes before its children
+/// and pushes its children onto `steps` with the text between and after them.
+fn generate(new_node: &NewASTNode, options: &EmitOptions, output: &mut Output) -> fmt::Result {
+    let pretty = options.mode == EmitMode::Pretty;
+    let (statement_separator, argument_separator) = match options.mode {
+        EmitMode::Compact => ("\n", ","),
+        EmitMode::Pretty => ("\n\n", ", "),
+        EmitMode::Minify => (";", ","),
+    };
+
+    let mut steps: Vec<Step> = vec![Step::Enter { node: new_node, suffix: 0 }];
+    // Depth of calls that fit on the rest of their line, whose arguments then fit too.
+    let mut flat_depth = 0;
+
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Enter { node, suffix } => match node {
+                NewASTNode::NewProgram(new_program) => {
+                    for (index, statement) in new_program.body.iter().enumerate().rev() {
+                        steps.push(Step::Enter { node: statement, suffix: 0 });
+                        if index > 0 {
+                            steps.push(Step::Text(statement_separator));
+                        }
+                    }
+                }
+                NewASTNode::ExpressionStatement(expression_statement) => {
+                    if pretty {
+                        steps.push(Step::Text(";"));
+                    }
+                    steps.push(Step::Enter { node: &expression_statement.expression, suffix: suffix + usize::from(pretty) });
+                }
+                NewASTNode::CallExpressionWithCallee(call_expression_with_callee) if call_expression_with_callee.callee.name == IF && call_expression_with_callee.arguments.len() == 3 => {
+                    // JavaScript has no `if` function, and a conditional only evaluates the branch taken.
+                    let (question, colon) = if options.mode == EmitMode::Minify { ("?", ":") } else { (" ? ", " : ") };
+                    let arguments = &call_expression_with_callee.arguments;
+                    output.map(call_expression_with_callee.span, None);
+                    output.write("(")?;
+                    steps.extend([
+                        Step::Text(")"),
+                        Step::Enter { node: &arguments[2], suffix: suffix + 1 },
+                        Step::Text(colon),
+                        Step::Enter { node: &arguments[1], suffix: 0 },
+                        Step::Text(question),
+                        Step::Enter { node: &arguments[0], suffix: 0 },
+                    ]);
+                }
+                NewASTNode::CallExpressionWithCallee(call_expression_with_callee) => {
+                    let callee = &call_expression_with_callee.callee;
+                    let arguments = &call_expression_with_callee.arguments;
+                    let available = options.line_width.saturating_sub(output.column + suffix);
+                    output.map(callee.span, Some(&callee.name));
+                    output.write(&callee.name)?;
+                    output.map(call_expression_with_callee.span, None);
+                    output.write("(")?;
+
+                    if !pretty || flat_depth > 0 || fits(node, available) {
+                        if pretty {
+                            flat_depth += 1;
+                            steps.push(Step::EndFlat);
+                        }
+                        steps.push(Step::Text(")"));
+                        for (index, argument) in arguments.iter().enumerate().rev() {
+                            steps.push(Step::Enter { node: argument, suffix: 0 });
+                            if index > 0 {
+                                steps.push(Step::Text(argument_separator));
+                            }
+                        }
+                    } else {
+                        steps.extend([Step::Text(")"), Step::Newline, Step::Dedent]);
+                        for (index, argument) in arguments.iter().enumerate().rev() {
+                            let last = index + 1 == arguments.len();
+                            if !last {
+                                steps.push(Step::Text(","));
+                            }
+                            steps.push(Step::Enter { node: argument, suffix: usize::from(!last) });
+                            steps.push(Step::Newline);
+                        }
+                        steps.push(Step::Indent);
+                    }
+                }
+                NewASTNode::Identifier(identifier) => {
+                    output.map(identifier.span, Some(&identifier.name));
+                    output.write_quoted(&identifier.name)?;
+                }
+                NewASTNode::NumberLiteral(number_literal) => {
+                    output.map(number_literal.span, None);
+                    output.write_quoted(&number_literal.value)?;
+                }
+                NewASTNode::StringLiteral(string_literal) => {
+                    output.map(string_literal.span, None);
+                    output.write_quoted(&string_literal.value)?;
+                }
+            },
+            Step::Text(text) => output.write(text)?,
+            Step::Newline => {
+                output.write("\n")?;
+                output.write_indentation(output.indent * options.indent)?;
+            }
+            Step::Indent => output.indent += 1,
+            Step::Dedent => output.indent -= 1,
+            Step::EndFlat => flat_depth -= 1,
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `new_node` fits in `available` columns on one line in `pretty` mode.
+/// Stops counting once it does not, so checking every call of a deeply nested one stays cheap.
+fn fits(new_node: &NewASTNode, available: usize) -> bool {
+    let mut nodes: Vec<&NewASTNode> = vec![new_node];
+    let mut width = 0;
+
+    while let Some(node) = nodes.pop() {
+        width += match node {
+            NewASTNode::NewProgram(new_program) => {
+                nodes.extend(&new_program.body);
+                0
+            }
+            NewASTNode::ExpressionStatement(expression_statement) => {
+                nodes.push(&expression_statement.expression);
+                0
+            }
+            NewASTNode::CallExpressionWithCallee(call_expression_with_callee) => {
+                let arguments = &call_expression_with_callee.arguments;
+                nodes.extend(arguments);
+                call_expression_with_callee.callee.name.chars().count() + 2 + 2 * arguments.len().saturating_sub(1)
+            }
+            NewASTNode::Identifier(identifier) => identifier.name.chars().count() + 2,
+            NewASTNode::NumberLiteral(number_literal) => number_literal.value.chars().count() + 2,
+            NewASTNode::StringLiteral(string_literal) => string_literal.value.chars().count() + 2,
+        };
+        if width > available {
+            return false;
+        }
+    }
+
+    true
 }
 
-fn generate(new_node: &NewASTNode) -> String {
-    match new_node {
-        NewASTNode::NewProgram(new_program) => {
-            let body = &new_program.body;
+#[cfg(test)]
+mod code_generator_tests {
+    use crate::span::{Position, Span};
+    use crate::ast::{ASTNodeType, CallExpressionWithCallee, Identifier, NewASTNode, NewProgram, NumberLiteral};
+    use std::io;
+    use crate::code_generator::{generate_code, generate_code_with_options, generate_code_with_source_map, CodeWriter, EmitMode, EmitOptions};
+    use crate::test_support::new_ast::{call, number, program, statement, string};
+
+    #[test]
+    fn test_generate_program() {
+        let new_program = program(vec![
+            statement(call("add", vec![number("2"), call("subtract", vec![number("4"), number("2")])])),
+            statement(call("fullName", vec![string("hoge"), string("foo")])),
+        ]);
+
+        let code = generate_code(new_program);
+
+        assert_eq!(code, "add('2',subtract('4','2'))\nfullName('hoge','foo')");
+    }
+
+    #[test]
+    fn test_generate_nested_calls() {
+        let mut new_node = number("1");
+        for _ in 0..500 {
+            new_node = call("add", vec![new_node]);
+        }
+
+        let code = generate_code(new_node);
+
+        assert_eq!(code, format!("{}'1'{}", "add(".repeat(500), ")".repeat(500)));
+    }
+
+    #[test]
+    fn test_generate_if_as_conditional() {
+        let new_node = call("if", vec![call("lt", vec![number("1"), number("2")]), string("yes"), call("if", vec![number("0"), string("a"), string("b")])]);
+
+        let compact = generate_code(new_node.clone());
+        let minified = generate_code_with_options(&new_node, &EmitOptions { mode: EmitMode::Minify, ..EmitOptions::default() });
+
+        assert_eq!(compact, "(lt('1','2') ? 'yes' : ('0' ? 'a' : 'b'))");
+        assert_eq!(minified, "(lt('1','2')?'yes':('0'?'a':'b'))");
+    }
+
+    fn span(line: usize, column: usize, length: usize) -> Span {
+        let start = Position { offset: column, line, column };
+
+        Span::new(start, Position { offset: column + length, ..start })
+    }
+
+    #[test]
+    fn test_generate_source_map() {
+        // (add 1
+        //   2)
+        let new_program = NewASTNode::NewProgram(NewProgram {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                statement(NewASTNode::CallExpressionWithCallee(CallExpressionWithCallee {
+                    node_type: ASTNodeType::CallExpression,
+                    span: span(1, 0, 11),
+                    callee: Identifier { node_type: ASTNodeType::Identifier, span: span(1, 1, 3), name: "add".to_string() },
+                    arguments: vec![
+                        NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: span(1, 5, 1), value: "1".to_string() }),
+                        NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: span(2, 2, 1), value: "2".to_string() }),
+                    ],
+                })),
+                statement(number("3")),
+            ],
+        });
+
+        let (code, source_map) = generate_code_with_source_map(&new_program, &EmitOptions::default(), "input.lisp", None);
+
+        assert_eq!(code, "add('1','2')\n'3'");
+        assert_eq!(source_map.names, vec!["add"]);
+        // add -> 1:1, ( -> 1:0, '1' -> 1:5, '2' -> 2:2; '3' has no span.
+        assert_eq!(source_map.mappings, "AAACA,GAAD,CAAK,IACH");
+    }
+
+    fn sample_program() -> NewASTNode {
+        program(vec![
+            statement(call("add", vec![number("2"), call("subtract", vec![number("4"), number("2")])])),
+            statement(call("fullName", vec![string("hoge")])),
+        ])
+    }
+
+    #[test]
+    fn test_generate_pretty() {
+        let options = EmitOptions { mode: EmitMode::Pretty, indent: 2, line_width: 20 };
+
+        let code = generate_code_with_options(&sample_program(), &options);
+
+        assert_eq!(code, "add(\n  '2',\n  subtract('4', '2')\n);\n\nfullName('hoge');");
+    }
 
-            let codes = body.iter().map(generate).collect::<Vec<String>>();
+    #[test]
+    fn test_generate_minify() {
+        let options = EmitOptions { mode: EmitMode::Minify, ..EmitOptions::default() };
 
-            codes.join("\n")
+        let code = generate_code_with_options(&sample_program(), &options);
+
+        assert_eq!(code, "add('2',subtract('4','2'));fullName('hoge')");
+    }
+
+    #[test]
+    fn test_generate_nested_calls_with_options() {
+        let mut new_node = number("1");
+        for _ in 0..500 {
+            new_node = call("add", vec![new_node]);
         }
-        NewASTNode::ExpressionStatement(expression_statement) => {
-            let expression = &expression_statement.expression;
-            let code = generate(expression);
 
-            code
+        let minified = generate_code_with_options(&new_node, &EmitOptions { mode: EmitMode::Minify, ..EmitOptions::default() });
+        let pretty = generate_code_with_options(&new_node, &EmitOptions { mode: EmitMode::Pretty, ..EmitOptions::default() });
+        let lines = pretty.lines().collect::<Vec<&str>>();
+
+        assert_eq!(minified, format!("{}'1'{}", "add(".repeat(500), ")".repeat(500)));
+        assert_eq!(lines.len(), 1001);
+        assert_eq!(lines[1], "  add(");
+        assert_eq!(lines[500], format!("{}'1'", " ".repeat(1000)));
+        assert_eq!(lines[999], "  )");
+        assert_eq!(lines[1000], ")");
+    }
+
+    #[test]
+    fn test_code_writer() {
+        let mut new_node = number("1");
+        for index in 0..300 {
+            new_node = call("add", vec![new_node, number(&index.to_string())]);
         }
-        NewASTNode::CallExpressionWithCallee(call_expression_with_callee) => {
-            let callee = &call_expression_with_callee.callee;
-            let arguments = &call_expression_with_callee.arguments;
+        let options = EmitOptions { mode: EmitMode::Pretty, ..EmitOptions::default() };
+
+        let mut code_writer = CodeWriter::new();
+        let mut first: Vec<u8> = vec![];
+        let mut second: Vec<u8> = vec![];
+        code_writer.write(&new_node, &options, &mut first).unwrap();
+        code_writer.write(&new_node, &options, &mut second).unwrap();
 
-            let codes = arguments.iter().map(generate).collect::<Vec<String>>();
+        let expected = generate_code_with_options(&new_node, &options);
+        assert_eq!(String::from_utf8(first).unwrap(), expected);
+        assert_eq!(String::from_utf8(second).unwrap(), expected);
+    }
+
+    struct FailingWriter;
+
+    impl io::Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+        }
 
-            format!("{}({})", callee.name, codes.join(","))
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
-        NewASTNode::Identifier(identifier) => format!("'{}'", identifier.name),
-        NewASTNode::NumberLiteral(number_literal) => format!("'{}'", number_literal.value),
-        NewASTNode::StringLiteral(string_literal) => format!("'{}'", string_literal.value),
+    }
+
+    #[test]
+    fn test_code_writer_returns_io_error() {
+        let result_error = CodeWriter::new().write(&sample_program(), &EmitOptions::default(), &mut FailingWriter).unwrap_err();
+
+        assert_eq!(result_error.kind(), io::ErrorKind::BrokenPipe);
     }
 }
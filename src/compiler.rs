@@ -1,11 +1,27 @@
+use std::path::Path;
+use crate::ast::{NewASTNode, Program};
+use crate::code_generator::generate_code;
+use crate::module::{bundle, load_program};
+use crate::optimizer::{optimize, OptLevel};
 use crate::parser::parser;
 use crate::tokenizer::tokenizer;
 use crate::transformer::transformer;
+use crate::types::check;
 
-pub fn compiler(code: String) -> anyhow::Result<()> {
+/// Imports are resolved relative to the current directory.
+pub fn compiler(code: String, opt_level: OptLevel) -> anyhow::Result<String> {
     let tokens = tokenizer(code)?;
-    let program = parser(tokens)?;
-    let new_program = transformer(program);
+    let program = bundle(load_program(Path::new("<input>"), parser(tokens)?)?);
 
-    Ok(())
-}
\ No newline at end of file
+    compile_program(program, opt_level)
+}
+
+/// Compiles a program whose macros are already expanded.
+pub fn compile_program(program: Program, opt_level: OptLevel) -> anyhow::Result<String> {
+    check(&program)?;
+    let program = optimize(program, opt_level);
+    let new_program = transformer(program)?;
+    let output = generate_code(NewASTNode::NewProgram(new_program));
+
+    Ok(output)
+}
@@ -0,0 +1,202 @@
+use crate::ast::{ASTNode, NewASTNode, NewProgram, Program};
+use crate::traverser::{NewVisitors, Visitors};
+
+/// Renders the source AST as a Graphviz DOT graph.
+/// Nodes whose type has a visitor in `highlight` are filled, to show what a pass touches.
+pub fn to_dot(program: &Program, highlight: Option<&Visitors>) -> String {
+    let mut graph = DotGraph::new();
+    let root = graph.add_node("Program", None, false);
+
+    // (parent id, edge label, node) still to be rendered, next one last
+    let mut pending: Vec<(usize, String, &ASTNode)> = program.body.iter().enumerate().rev()
+        .map(|(index, child)| (root, format!("body[{}]", index), child.as_ref()))
+        .collect();
+
+    while let Some((parent, edge_label, node)) = pending.pop() {
+        let node_type = node.get_node_type();
+        let highlighted = highlight.map_or(false, |visitors| visitors.contains_key(&node_type));
+
+        let id = match node {
+            ASTNode::CallExpression(call_expression) => {
+                let id = graph.add_node(node_type.into(), Some(&call_expression.value), highlighted);
+                pending.extend(call_expression.params.iter().enumerate().rev()
+                    .map(|(index, child)| (id, format!("params[{}]", index), child.as_ref())));
+                id
+            }
+            ASTNode::NumberLiteral(number_literal) => graph.add_node(node_type.into(), Some(&number_literal.value), highlighted),
+            ASTNode::StringLiteral(string_literal) => graph.add_node(node_type.into(), Some(&string_literal.value), highlighted),
+            ASTNode::Identifier(identifier) => graph.add_node(node_type.into(), Some(&identifier.name), highlighted),
+            ASTNode::Program(_) | ASTNode::Root(_) => graph.add_node(node_type.into(), None, highlighted),
+        };
+        graph.add_edge(parent, id, &edge_label);
+    }
+
+    graph.finish("AST")
+}
+
+/// Renders the transformed AST as a Graphviz DOT graph, highlighting like `to_dot`.
+pub fn new_to_dot(new_program: &NewProgram, highlight: Option<&NewVisitors>) -> String {
+    let mut graph = DotGraph::new();
+    let root = graph.add_node("Program", None, false);
+
+    let mut pending: Vec<(usize, String, &NewASTNode)> = new_program.body.iter().enumerate().rev()
+        .map(|(index, child)| (root, format!("body[{}]", index), child))
+        .collect();
+
+    while let Some((parent, edge_label, node)) = pending.pop() {
+        let node_type = node.get_node_type();
+        let highlighted = highlight.map_or(false, |visitors| visitors.contains_key(&node_type));
+
+        let id = match node {
+            NewASTNode::NewProgram(_) => graph.add_node(node_type.into(), None, highlighted),
+            NewASTNode::ExpressionStatement(expression_statement) => {
+                let id = graph.add_node(node_type.into(), None, highlighted);
+                pending.push((id, "expression".to_string(), &expression_statement.expression));
+                id
+            }
+            NewASTNode::CallExpressionWithCallee(call_expression) => {
+                let id = graph.add_node(node_type.into(), None, highlighted);
+                let callee_highlighted = highlight.map_or(false, |visitors| visitors.contains_key(&call_expression.callee.node_type));
+                let callee = graph.add_node(call_expression.callee.node_type.into(), Some(&call_expression.callee.name), callee_highlighted);
+                graph.add_edge(id, callee, "callee");
+                pending.extend(call_expression.arguments.iter().enumerate().rev()
+                    .map(|(index, child)| (id, format!("arguments[{}]", index), child)));
+                id
+            }
+            NewASTNode::Identifier(identifier) => graph.add_node(node_type.into(), Some(&identifier.name), highlighted),
+            NewASTNode::NumberLiteral(number_literal) => graph.add_node(node_type.into(), Some(&number_literal.value), highlighted),
+            NewASTNode::StringLiteral(string_literal) => graph.add_node(node_type.into(), Some(&string_literal.value), highlighted),
+        };
+        graph.add_edge(parent, id, &edge_label);
+    }
+
+    graph.finish("NewAST")
+}
+
+struct DotGraph {
+    lines: Vec<String>,
+    node_count: usize,
+}
+
+impl DotGraph {
+    fn new() -> DotGraph {
+        DotGraph { lines: vec![], node_count: 0 }
+    }
+
+    fn add_node(&mut self, node_type: &str, value: Option<&str>, highlighted: bool) -> usize {
+        let id = self.node_count;
+        self.node_count += 1;
+
+        let label = match value {
+            Some(value) => format!("{}\\n{}", escape(node_type), escape(value)),
+            None => escape(node_type),
+        };
+        let style = if highlighted { ", style=filled, fillcolor=lightblue" } else { "" };
+        self.lines.push(format!("  n{} [label=\"{}\"{}];", id, label, style));
+
+        id
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, label: &str) {
+        self.lines.push(format!("  n{} -> n{} [label=\"{}\"];", from, to, escape(label)));
+    }
+
+    fn finish(self, name: &str) -> String {
+        format!("digraph {} {{\n  node [shape=box];\n{}\n}}", name, self.lines.join("\n"))
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod dot_tests {
+    use std::rc::Rc;
+    use crate::ast::{ASTNode, ASTNodeType, CallExpression, CallExpressionWithCallee, ExpressionStatement, Identifier, NewASTNode, NewProgram, NumberLiteral, Program, StringLiteral};
+    use crate::dot::{new_to_dot, to_dot};
+    use crate::span::Span;
+    use crate::traverser::{NewVisitors, Visitors};
+    use crate::visitor::{MockNewVisitor, MockVisitor};
+
+    #[test]
+    fn test_program_to_dot() {
+        let program = Program {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                Rc::new(ASTNode::CallExpression(CallExpression {
+                    node_type: ASTNodeType::CallExpression,
+                    span: Span::default(),
+                    value: "fullName".to_string(),
+                    callee_span: Span::default(),
+                    params: vec![
+                        Rc::new(ASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "say \"hi\"".to_string() })),
+                        Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "2".to_string() })),
+                    ],
+                })),
+            ],
+        };
+
+        let mut visitors: Visitors = Visitors::new();
+        visitors.insert(ASTNodeType::NumberLiteral, Box::new(MockVisitor::new()));
+
+        let dot = to_dot(&program, Some(&visitors));
+
+        assert_eq!(dot, [
+            "digraph AST {",
+            "  node [shape=box];",
+            "  n0 [label=\"Program\"];",
+            "  n1 [label=\"CallExpression\\nfullName\"];",
+            "  n0 -> n1 [label=\"body[0]\"];",
+            "  n2 [label=\"StringLiteral\\nsay \\\"hi\\\"\"];",
+            "  n1 -> n2 [label=\"params[0]\"];",
+            "  n3 [label=\"NumberLiteral\\n2\", style=filled, fillcolor=lightblue];",
+            "  n1 -> n3 [label=\"params[1]\"];",
+            "}",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_new_program_to_dot() {
+        let new_program = NewProgram {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                NewASTNode::ExpressionStatement(ExpressionStatement {
+                    node_type: ASTNodeType::ExpressionStatement,
+                    span: Span::default(),
+                    expression: Box::new(NewASTNode::CallExpressionWithCallee(CallExpressionWithCallee {
+                        node_type: ASTNodeType::CallExpression,
+                        span: Span::default(),
+                        callee: Identifier { node_type: ASTNodeType::Identifier, span: Span::default(), name: "add".to_string() },
+                        arguments: vec![
+                            NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "1".to_string() }),
+                        ],
+                    })),
+                }),
+            ],
+        };
+
+        let mut visitors: NewVisitors = NewVisitors::new();
+        visitors.insert(ASTNodeType::CallExpression, Box::new(MockNewVisitor::new()));
+        visitors.insert(ASTNodeType::Identifier, Box::new(MockNewVisitor::new()));
+
+        let dot = new_to_dot(&new_program, Some(&visitors));
+
+        assert_eq!(dot, [
+            "digraph NewAST {",
+            "  node [shape=box];",
+            "  n0 [label=\"Program\"];",
+            "  n1 [label=\"ExpressionStatement\"];",
+            "  n0 -> n1 [label=\"body[0]\"];",
+            "  n2 [label=\"CallExpression\", style=filled, fillcolor=lightblue];",
+            "  n3 [label=\"Identifier\\nadd\", style=filled, fillcolor=lightblue];",
+            "  n2 -> n3 [label=\"callee\"];",
+            "  n1 -> n2 [label=\"expression\"];",
+            "  n4 [label=\"NumberLiteral\\n1\"];",
+            "  n2 -> n4 [label=\"arguments[0]\"];",
+            "}",
+        ].join("\n"));
+    }
+}
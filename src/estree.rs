@@ -0,0 +1,231 @@
+use serde_json::{json, Map, Number, Value};
+use crate::ast::{NewASTNode, NewProgram};
+use crate::span::Span;
+
+/// Exports the transformed AST as ESTree JSON.
+/// `source` is the code the AST was parsed from; literals take their `raw` text from it
+/// when given, and otherwise get a `raw` rebuilt from their value.
+/// `range` offsets count chars, and `loc`/`range` are left out of nodes without a span.
+pub fn to_estree(new_program: &NewProgram, source: Option<&str>) -> Value {
+    let exporter = EstreeExporter {
+        source: source.map(|source| source.chars().collect()),
+    };
+
+    exporter.program(new_program)
+}
+
+struct EstreeExporter {
+    source: Option<Vec<char>>,
+}
+
+impl EstreeExporter {
+    fn program(&self, new_program: &NewProgram) -> Value {
+        let body = new_program.body.iter().map(|node| self.statement(node)).collect::<Vec<Value>>();
+
+        self.node("Program", new_program.span, json!({
+            "sourceType": "script",
+            "body": body,
+        }))
+    }
+
+    /// ESTree only allows statements in a program body, so top-level expressions get wrapped.
+    fn statement(&self, node: &NewASTNode) -> Value {
+        match node {
+            NewASTNode::ExpressionStatement(expression_statement) => self.node("ExpressionStatement", expression_statement.span, json!({
+                "expression": self.expression(&expression_statement.expression),
+            })),
+            NewASTNode::NewProgram(new_program) => self.program(new_program),
+            expression => {
+                let span = expression.get_span();
+                let expression = self.expression(expression);
+
+                self.node("ExpressionStatement", span, json!({ "expression": expression }))
+            }
+        }
+    }
+
+    fn expression(&self, node: &NewASTNode) -> Value {
+        match node {
+            NewASTNode::CallExpressionWithCallee(call_expression) => {
+                let callee = self.node("Identifier", call_expression.callee.span, json!({ "name": call_expression.callee.name }));
+                let arguments = call_expression.arguments.iter().map(|argument| self.expression(argument)).collect::<Vec<Value>>();
+
+                self.node("CallExpression", call_expression.span, json!({
+                    "callee": callee,
+                    "arguments": arguments,
+                    "optional": false,
+                }))
+            }
+            NewASTNode::Identifier(identifier) => self.node("Identifier", identifier.span, json!({ "name": identifier.name })),
+            NewASTNode::NumberLiteral(number_literal) => {
+                let value = number_value(&number_literal.value);
+                let raw = self.raw(number_literal.span, |raw| raw == number_literal.value).unwrap_or_else(|| number_literal.value.clone());
+
+                self.node("Literal", number_literal.span, json!({ "value": value, "raw": raw }))
+            }
+            NewASTNode::StringLiteral(string_literal) => {
+                let raw = self.raw(string_literal.span, |raw| is_string_literal(raw, &string_literal.value))
+                    .unwrap_or_else(|| Value::String(string_literal.value.clone()).to_string());
+
+                self.node("Literal", string_literal.span, json!({ "value": string_literal.value, "raw": raw }))
+            }
+            NewASTNode::ExpressionStatement(expression_statement) => self.expression(&expression_statement.expression),
+            NewASTNode::NewProgram(new_program) => self.program(new_program),
+        }
+    }
+
+    /// Builds `{ type, ...fields, loc, range }`.
+    fn node(&self, node_type: &str, span: Span, fields: Value) -> Value {
+        let mut node = Map::new();
+        node.insert("type".to_string(), Value::String(node_type.to_string()));
+        if let Value::Object(fields) = fields {
+            node.extend(fields);
+        }
+
+        if !span.is_empty() {
+            node.insert("loc".to_string(), json!({
+                "start": { "line": span.start.line, "column": span.start.column },
+                "end": { "line": span.end.line, "column": span.end.column },
+            }));
+            node.insert("range".to_string(), json!([span.start.offset, span.end.offset]));
+        }
+
+        Value::Object(node)
+    }
+
+    /// The source text at `span`, if `is_literal` accepts it as the literal of the node.
+    /// Literals the optimizer folded keep the span of the call they replace, which is not.
+    fn raw(&self, span: Span, is_literal: impl Fn(&str) -> bool) -> Option<String> {
+        let source = self.source.as_ref()?;
+        if span.is_empty() || span.end.offset > source.len() {
+            return None;
+        }
+
+        let raw = source[span.start.offset..span.end.offset].iter().collect::<String>();
+        if is_literal(&raw) { Some(raw) } else { None }
+    }
+}
+
+/// Whether `raw` is `value` in quotes, as the tokenizer reads strings.
+fn is_string_literal(raw: &str, value: &str) -> bool {
+    let is_quote = |ch: char| ch == '"' || ch == '\'';
+
+    raw.strip_prefix(is_quote)
+        .and_then(|raw| raw.strip_suffix(is_quote))
+        == Some(value)
+}
+
+fn number_value(value: &str) -> Value {
+    if let Ok(integer) = value.parse::<u64>() {
+        return Value::Number(Number::from(integer));
+    }
+
+    value.parse::<f64>()
+        .ok()
+        .and_then(Number::from_f64)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod estree_tests {
+    use serde_json::{json, Value};
+    use crate::ast::{ASTNodeType, CallExpressionWithCallee, ExpressionStatement, Identifier, NewASTNode, NewProgram, NumberLiteral, StringLiteral};
+    use crate::estree::to_estree;
+    use crate::span::{Position, Span};
+
+    /// A span on the first line, where the column equals the offset.
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(Position { offset: start, line: 1, column: start }, Position { offset: end, line: 1, column: end })
+    }
+
+    fn loc(start: usize, end: usize) -> Value {
+        json!({ "start": { "line": 1, "column": start }, "end": { "line": 1, "column": end } })
+    }
+
+    #[test]
+    fn test_estree_with_source() {
+        let code = "(add 2 'hoge')";
+        let new_program = NewProgram {
+            node_type: ASTNodeType::Program,
+            span: span(0, 14),
+            body: vec![
+                NewASTNode::ExpressionStatement(ExpressionStatement {
+                    node_type: ASTNodeType::ExpressionStatement,
+                    span: span(0, 14),
+                    expression: Box::new(NewASTNode::CallExpressionWithCallee(CallExpressionWithCallee {
+                        node_type: ASTNodeType::CallExpression,
+                        span: span(0, 14),
+                        callee: Identifier { node_type: ASTNodeType::Identifier, span: span(1, 4), name: "add".to_string() },
+                        arguments: vec![
+                            NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: span(5, 6), value: "2".to_string() }),
+                            NewASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: span(7, 13), value: "hoge".to_string() }),
+                        ],
+                    })),
+                }),
+            ],
+        };
+
+        let estree = to_estree(&new_program, Some(code));
+
+        assert_eq!(estree, json!({
+            "type": "Program",
+            "sourceType": "script",
+            "body": [{
+                "type": "ExpressionStatement",
+                "expression": {
+                    "type": "CallExpression",
+                    "callee": { "type": "Identifier", "name": "add", "loc": loc(1, 4), "range": [1, 4] },
+                    "arguments": [
+                        { "type": "Literal", "value": 2, "raw": "2", "loc": loc(5, 6), "range": [5, 6] },
+                        { "type": "Literal", "value": "hoge", "raw": "'hoge'", "loc": loc(7, 13), "range": [7, 13] },
+                    ],
+                    "optional": false,
+                    "loc": loc(0, 14),
+                    "range": [0, 14],
+                },
+                "loc": loc(0, 14),
+                "range": [0, 14],
+            }],
+            "loc": loc(0, 14),
+            "range": [0, 14],
+        }));
+    }
+
+    #[test]
+    fn test_estree_wraps_top_level_literal() {
+        let new_program = NewProgram {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "1.5".to_string() }),
+            ],
+        };
+
+        let estree = to_estree(&new_program, None);
+
+        assert_eq!(estree, json!({
+            "type": "Program",
+            "sourceType": "script",
+            "body": [{
+                "type": "ExpressionStatement",
+                "expression": { "type": "Literal", "value": 1.5, "raw": "1.5" },
+            }],
+        }));
+    }
+
+    #[test]
+    fn test_estree_raw_without_source() {
+        let new_program = NewProgram {
+            node_type: ASTNodeType::Program,
+            span: span(0, 7),
+            body: vec![
+                NewASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: span(0, 7), value: "it's".to_string() }),
+            ],
+        };
+
+        let estree = to_estree(&new_program, None);
+
+        assert_eq!(estree["body"][0]["expression"]["raw"], json!("\"it's\""));
+    }
+}
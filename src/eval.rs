@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use thiserror::Error;
+use crate::ast::{ASTNode, CallExpression, Program};
+use crate::span::{Position, Span};
+use crate::suggest::Suggestions;
+
+/// A runtime value. Number literals evaluate to 64-bit integers.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Number(i64),
+    String(String),
+    Bool(bool),
+    Nil,
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Nil => "nil",
+        }
+    }
+}
+
+/// Formats the value as `print` writes it: strings without quotes.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(string) => write!(f, "{}", string),
+            Value::Bool(bool) => write!(f, "{}", bool),
+            Value::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+/// `(if condition then else)` is a special form rather than a builtin.
+pub const IF: &str = "if";
+
+/// A builtin gets the evaluated arguments and the output `print` writes to.
+pub type Builtin = fn(&[Value], &mut dyn Write) -> Result<Value, BuiltinError>;
+
+/// Why a builtin failed; the interpreter attaches the span of the call or argument.
+#[derive(Debug, PartialEq)]
+pub enum BuiltinError {
+    Arity(usize),
+    /// Argument at the index had the wrong type; holds the expected type name.
+    Type(usize, &'static str),
+    Overflow,
+    DivisionByZero,
+    Output(String),
+}
+
+pub struct Environment {
+    builtins: HashMap<String, Builtin>,
+}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment::new()
+    }
+}
+
+impl Environment {
+    /// `add`, `subtract`, `multiply`, `divide`, `concat`, `print` and the comparisons `eq`, `lt`, `le`, `gt`, `ge`.
+    pub fn new() -> Environment {
+        let mut environment = Environment { builtins: HashMap::new() };
+        environment.define("add", |args, _| arithmetic(args, i64::checked_add));
+        environment.define("subtract", |args, _| arithmetic(args, i64::checked_sub));
+        environment.define("multiply", |args, _| arithmetic(args, i64::checked_mul));
+        environment.define("divide", |args, _| {
+            let (left, right) = numbers(args)?;
+            if right == 0 {
+                return Err(BuiltinError::DivisionByZero);
+            }
+            left.checked_div(right).map(Value::Number).ok_or(BuiltinError::Overflow)
+        });
+        environment.define("concat", |args, _| {
+            let strings = args.iter().enumerate()
+                .map(|(index, arg)| match arg {
+                    Value::String(string) => Ok(string.as_str()),
+                    _ => Err(BuiltinError::Type(index, "string")),
+                })
+                .collect::<Result<Vec<&str>, BuiltinError>>()?;
+            Ok(Value::String(strings.concat()))
+        });
+        environment.define("print", |args, out| {
+            let line = args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>().join(" ");
+            writeln!(out, "{}", line).map_err(|error| BuiltinError::Output(error.to_string()))?;
+            Ok(Value::Nil)
+        });
+        environment.define("eq", |args, _| {
+            let [left, right] = args else {
+                return Err(BuiltinError::Arity(2));
+            };
+            Ok(Value::Bool(left == right))
+        });
+        environment.define("lt", |args, _| numbers(args).map(|(left, right)| Value::Bool(left < right)));
+        environment.define("le", |args, _| numbers(args).map(|(left, right)| Value::Bool(left <= right)));
+        environment.define("gt", |args, _| numbers(args).map(|(left, right)| Value::Bool(left > right)));
+        environment.define("ge", |args, _| numbers(args).map(|(left, right)| Value::Bool(left >= right)));
+
+        environment
+    }
+
+    /// The defined names and `if`, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.builtins.keys().map(String::as_str).chain([IF])
+    }
+
+    /// The error for a call to `name`, which is not defined, with the defined names nearest to it.
+    pub fn unknown_function(&self, name: &str, callee_span: Span) -> EvalError {
+        EvalError::UnknownFunction(name.to_string(), callee_span.start, Suggestions::for_name(name, callee_span, self.names()))
+    }
+
+    pub fn define(&mut self, name: &str, builtin: Builtin) {
+        self.builtins.insert(name.to_string(), builtin);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Builtin> {
+        self.builtins.get(name).copied()
+    }
+}
+
+fn numbers(args: &[Value]) -> Result<(i64, i64), BuiltinError> {
+    match args {
+        [Value::Number(left), Value::Number(right)] => Ok((*left, *right)),
+        [Value::Number(_), _] => Err(BuiltinError::Type(1, "number")),
+        [_, _] => Err(BuiltinError::Type(0, "number")),
+        _ => Err(BuiltinError::Arity(2)),
+    }
+}
+
+fn arithmetic(args: &[Value], operator: fn(i64, i64) -> Option<i64>) -> Result<Value, BuiltinError> {
+    let (left, right) = numbers(args)?;
+    operator(left, right).map(Value::Number).ok_or(BuiltinError::Overflow)
+}
+
+/// Evaluates each top-level expression of `program` in order and returns their values.
+/// Arguments are evaluated left to right before the function is looked up.
+/// `print` writes to `out`.
+pub fn eval(program: &Program, environment: &Environment, out: &mut dyn Write) -> anyhow::Result<Vec<Value>, EvalError> {
+    let mut interpreter = Interpreter { environment, out };
+
+    program.body.iter().map(|node| interpreter.eval_node(node)).collect()
+}
+
+struct Interpreter<'a> {
+    environment: &'a Environment,
+    out: &'a mut dyn Write,
+}
+
+impl Interpreter<'_> {
+    fn eval_node(&mut self, node: &ASTNode) -> Result<Value, EvalError> {
+        match node {
+            ASTNode::CallExpression(call_expression) => self.eval_call(call_expression),
+            ASTNode::NumberLiteral(number_literal) => number_literal.value.parse()
+                .map(Value::Number)
+                .map_err(|_| EvalError::InvalidNumber(number_literal.value.clone(), number_literal.span.start)),
+            ASTNode::StringLiteral(string_literal) => Ok(Value::String(string_literal.value.clone())),
+            ASTNode::Identifier(identifier) => Err(EvalError::UnboundSymbol(identifier.name.clone(), identifier.span.start)),
+            ASTNode::Program(program) => Ok(program.body.iter()
+                .map(|node| self.eval_node(node))
+                .collect::<Result<Vec<Value>, EvalError>>()?
+                .pop()
+                .unwrap_or(Value::Nil)),
+            ASTNode::Root(_) => Ok(Value::Nil),
+        }
+    }
+
+    fn eval_call(&mut self, call_expression: &CallExpression) -> Result<Value, EvalError> {
+        if call_expression.value == IF {
+            return self.eval_if(call_expression);
+        }
+
+        let args = call_expression.params.iter()
+            .map(|param| self.eval_node(param))
+            .collect::<Result<Vec<Value>, EvalError>>()?;
+        let builtin = self.environment.get(&call_expression.value)
+            .ok_or_else(|| self.environment.unknown_function(&call_expression.value, call_expression.callee_span))?;
+
+        builtin(&args, self.out).map_err(|error| {
+            let arg_spans = call_expression.params.iter().map(|param| param.get_span()).collect::<Vec<Span>>();
+            builtin_error(error, &call_expression.value, call_expression.span, &arg_spans, &args)
+        })
+    }
+
+    /// Only the branch picked by the condition is evaluated.
+    fn eval_if(&mut self, call_expression: &CallExpression) -> Result<Value, EvalError> {
+        let [condition, then_branch, else_branch] = call_expression.params.as_slice() else {
+            return Err(EvalError::ArityMismatch(IF.to_string(), 3, call_expression.params.len(), call_expression.span.start));
+        };
+
+        match self.eval_node(condition)? {
+            Value::Bool(true) => self.eval_node(then_branch),
+            Value::Bool(false) => self.eval_node(else_branch),
+            value => Err(EvalError::TypeMismatch(IF.to_string(), "bool", value.type_name(), condition.get_span().start)),
+        }
+    }
+}
+
+/// Attaches the span of the call, or of the offending argument, to a builtin failure.
+pub fn builtin_error(error: BuiltinError, name: &str, span: Span, arg_spans: &[Span], args: &[Value]) -> EvalError {
+    match error {
+        BuiltinError::Arity(expected) => EvalError::ArityMismatch(name.to_string(), expected, args.len(), span.start),
+        BuiltinError::Type(index, expected) => {
+            let arg_span = arg_spans.get(index).copied().unwrap_or(span);
+            let found = args.get(index).map_or("nothing", Value::type_name);
+            EvalError::TypeMismatch(name.to_string(), expected, found, arg_span.start)
+        }
+        BuiltinError::Overflow => EvalError::Overflow(name.to_string(), span.start),
+        BuiltinError::DivisionByZero => EvalError::DivisionByZero(span.start),
+        BuiltinError::Output(message) => EvalError::Output(message, span.start),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Error)]
+pub enum EvalError {
+    #[error("Error: Unknown function: {0} at {1}{2}")]
+    UnknownFunction(String, Position, Suggestions),
+    #[error("Error: {0} expects {1} arguments but got {2} at {3}")]
+    ArityMismatch(String, usize, usize, Position),
+    #[error("Error: {0} expects a {1} but got a {2} at {3}")]
+    TypeMismatch(String, &'static str, &'static str, Position),
+    #[error("Error: Integer overflow in {0} at {1}")]
+    Overflow(String, Position),
+    #[error("Error: Division by zero at {0}")]
+    DivisionByZero(Position),
+    #[error("Error: Unbound symbol: {0} at {1}")]
+    UnboundSymbol(String, Position),
+    #[error("Error: Invalid number: {0} at {1}")]
+    InvalidNumber(String, Position),
+    #[error("Error: Cannot write output: {0} at {1}")]
+    Output(String, Position),
+}
+
+#[cfg(test)]
+mod eval_tests {
+    use crate::eval::{eval, Environment, EvalError, Value};
+    use crate::parser::parser;
+    use crate::tokenizer::tokenizer;
+
+    fn run(code: &str) -> (Result<Vec<Value>, EvalError>, String) {
+        let program = parser(tokenizer(code.to_string()).unwrap()).unwrap();
+        let mut out: Vec<u8> = vec![];
+
+        let result = eval(&program, &Environment::new(), &mut out);
+
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_eval_program() {
+        let (result, out) = run("
+            (add 2 (subtract 4 (multiply 1 2)))
+            (print (concat 'hoge' 'foo') (divide 9 3))
+            (eq (lt 1 2) (ge 3 3))
+            (if (gt 1 2) (print 'then') (concat 'else'))
+        ");
+
+        assert_eq!(result.unwrap(), vec![Value::Number(4), Value::Nil, Value::Bool(true), Value::String("else".to_string())]);
+        assert_eq!(out, "hogefoo 3\n");
+    }
+
+    #[test]
+    fn test_eval_errors_point_at_source() {
+        let errors = [
+            ("(print 1)\n(fullName 'hoge')", "Error: Unknown function: fullName at 2:2"),
+            ("(print (substract 2 1))", "Error: Unknown function: substract at 1:9 (did you mean subtract?)"),
+            ("(add 1 (subtract 2))", "Error: subtract expects 2 arguments but got 1 at 1:8"),
+            ("(add 1\n  'hoge')", "Error: add expects a number but got a string at 2:3"),
+            ("(multiply 9223372036854775807 2)", "Error: Integer overflow in multiply at 1:1"),
+            ("(divide 1 0)", "Error: Division by zero at 1:1"),
+            ("(if 1 2 3)", "Error: if expects a bool but got a number at 1:5"),
+            ("(if (eq 1 1) 2)", "Error: if expects 3 arguments but got 2 at 1:1"),
+        ];
+
+        for (code, message) in errors {
+            let (result, _) = run(code);
+
+            assert_eq!(result.unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_eval_stops_at_first_error() {
+        let (result, out) = run("(print 1) (divide 1 0) (print 2)");
+
+        assert!(result.is_err());
+        assert_eq!(out, "1\n");
+    }
+}
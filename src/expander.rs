@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use thiserror::Error;
+use crate::ast::{ASTNode, ASTNodeType, CallExpression, Identifier, NumberLiteral, Program, StringLiteral};
+use crate::functions::Arity;
+use crate::module::{EXPORT, IMPORT};
+use crate::parser::DEFAULT_MAX_DEPTH;
+use crate::resolver::{Diagnostic, Resolver};
+use crate::span::{Position, Span};
+
+pub const DEFMACRO: &str = "defmacro";
+pub const QUASIQUOTE: &str = "quasiquote";
+pub const UNQUOTE: &str = "unquote";
+pub const UNQUOTE_SPLICING: &str = "unquote-splicing";
+/// Marks the last parameter as taking the remaining arguments, as in `(defmacro m (a &rest more) ...)`.
+pub const REST: &str = "&rest";
+
+/// Macro calls that expand into macro calls more than this deep are rejected with
+/// `ExpandError::DepthExceeded`, which stops macros that expand into themselves.
+pub const DEFAULT_MAX_EXPANSION_DEPTH: usize = 100;
+
+/// A macro defined by `(defmacro name (params...) body)`.
+/// The body is a parameter or a quasiquoted template.
+#[derive(Debug)]
+struct Macro {
+    /// Span of the name in the definition.
+    span: Span,
+    params: Vec<String>,
+    rest: Option<String>,
+    body: Rc<ASTNode>,
+}
+
+/// The arguments a macro parameter stands for in one expansion.
+enum Binding {
+    One(Rc<ASTNode>),
+    Rest(Vec<Rc<ASTNode>>),
+}
+
+/// Expands calls to macros, between the parser and the transformer.
+/// Macros stay defined across calls to `expand`, so a REPL can define them in one form and use them in the next.
+#[derive(Debug)]
+pub struct Expander {
+    macros: HashMap<String, Rc<Macro>>,
+    max_depth: usize,
+    /// Symbols generated so far, to number the next one.
+    gensyms: usize,
+    /// Warnings about the programs expanded so far, until taken.
+    warnings: Vec<Diagnostic>,
+    /// Arguments of the macro calls being expanded, with the depth of the call they were written in.
+    argument_depths: HashMap<*const ASTNode, usize>,
+}
+
+impl Default for Expander {
+    fn default() -> Expander {
+        Expander::new()
+    }
+}
+
+/// Expands the macros `program` defines in the rest of it.
+pub fn expand(program: Program) -> anyhow::Result<Program, ExpandError> {
+    Expander::new().expand(program)
+}
+
+impl Expander {
+    pub fn new() -> Expander {
+        Expander::with_max_depth(DEFAULT_MAX_EXPANSION_DEPTH)
+    }
+
+    pub fn with_max_depth(max_depth: usize) -> Expander {
+        Expander { macros: HashMap::new(), max_depth, gensyms: 0, warnings: vec![], argument_depths: HashMap::new() }
+    }
+
+    /// Defines the top-level `defmacro`s of `program`, which are removed from it, and expands
+    /// calls to macros in the other nodes. Macros can only be called after their definition.
+    /// Fails on the first error `Resolver` finds in `program`, and keeps its warnings.
+    pub fn expand(&mut self, program: Program) -> anyhow::Result<Program, ExpandError> {
+        let mut resolver = Resolver::new();
+        let mut macros = self.macros.iter().collect::<Vec<(&String, &Rc<Macro>)>>();
+        macros.sort_by_key(|(name, _)| *name);
+        for (name, definition) in macros {
+            resolver.declare_macro(name, definition.span);
+        }
+        let resolution = resolver.resolve(&program);
+        if let Some(error) = resolution.errors().next() {
+            return Err(error.clone().into());
+        }
+        self.warnings.extend(resolution.diagnostics);
+
+        let mut body = vec![];
+        for node in &program.body {
+            match node.as_ref() {
+                ASTNode::CallExpression(call_expression) if call_expression.value == DEFMACRO => self.define(call_expression)?,
+                _ => {
+                    let expanded = self.expand_node(node, 0, false)?;
+                    // The parser limits the nesting of what later passes walk recursively; expansions must keep to it too.
+                    if nesting(&expanded) > DEFAULT_MAX_DEPTH {
+                        return Err(ExpandError::NestingTooDeep(DEFAULT_MAX_DEPTH));
+                    }
+                    body.push(expanded);
+                }
+            }
+        }
+
+        Ok(Program { node_type: program.node_type, span: program.span, body })
+    }
+
+    fn define(&mut self, defmacro: &CallExpression) -> Result<(), ExpandError> {
+        let definition = parse_defmacro(defmacro)?;
+
+        // Macro calls in the template are expanded here, so a macro can use macros its importers cannot see.
+        let body = match definition.body.as_ref() {
+            ASTNode::CallExpression(quasiquote) => Rc::new(ASTNode::CallExpression(CallExpression {
+                params: vec![self.expand_node(&quasiquote.params[0], 0, true)?],
+                ..quasiquote.clone()
+            })),
+            _ => definition.body.clone(),
+        };
+        let params = definition.params.into_iter().map(|(name, _)| name).collect();
+        let rest = definition.rest.map(|(name, _)| name);
+        self.macros.insert(definition.name.name.clone(), Rc::new(Macro { span: definition.name.span, params, rest, body }));
+        Ok(())
+    }
+
+    /// Defines the macro `name` of `other` here too, if `other` has one.
+    /// Fails if a different macro of that name is defined here already.
+    pub fn import_macro(&mut self, other: &Expander, name: &str) -> Result<(), ExpandError> {
+        let Some(definition) = other.macros.get(name) else {
+            return Ok(());
+        };
+        if let Some(existing) = self.macros.get(name).filter(|existing| !Rc::ptr_eq(existing, definition)) {
+            return Err(Diagnostic::DuplicateDefinition(name.to_string(), existing.span.start, definition.span.start).into());
+        }
+
+        self.macros.insert(name.to_string(), definition.clone());
+        Ok(())
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn defines(&self, name: &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    /// `depth` is the number of macro expansions `node` came out of.
+    /// In a `template`, symbols and quotes are kept for when the template is instantiated.
+    fn expand_node(&mut self, node: &Rc<ASTNode>, depth: usize, template: bool) -> Result<Rc<ASTNode>, ExpandError> {
+        let depth = self.argument_depths.get(&Rc::as_ptr(node)).copied().unwrap_or(depth);
+        let call_expression = match node.as_ref() {
+            ASTNode::CallExpression(call_expression) => call_expression,
+            ASTNode::Identifier(_) if template => return Ok(node.clone()),
+            ASTNode::Identifier(identifier) => return Err(ExpandError::UnboundSymbol(identifier.name.clone(), identifier.span.start)),
+            _ => return Ok(node.clone()),
+        };
+
+        let name = call_expression.value.as_str();
+        if name.is_empty() {
+            return Err(ExpandError::EmptyList(call_expression.span.start));
+        }
+        if name == DEFMACRO || name == IMPORT || name == EXPORT {
+            return Err(ExpandError::NotAtTopLevel(name.to_string(), call_expression.callee_span.start));
+        }
+        if name == QUASIQUOTE || name == UNQUOTE || name == UNQUOTE_SPLICING {
+            if template {
+                return Ok(node.clone());
+            }
+            return Err(ExpandError::QuoteOutsideMacro(name.to_string(), call_expression.callee_span.start));
+        }
+
+        let Some(definition) = self.macros.get(name).cloned() else {
+            let params = call_expression.params.iter()
+                .map(|param| self.expand_node(param, depth, template))
+                .collect::<Result<Vec<Rc<ASTNode>>, ExpandError>>()?;
+            return Ok(Rc::new(ASTNode::CallExpression(CallExpression { params, ..call_expression.clone() })));
+        };
+
+        if depth >= self.max_depth {
+            return Err(ExpandError::DepthExceeded(name.to_string(), self.max_depth, call_expression.callee_span.start));
+        }
+        let expansion = self.expand_call(&definition, call_expression)?;
+
+        // Only what the macro adds is one expansion deeper; the arguments stay as deep as the call,
+        // so nesting calls of a macro that is not recursive is not limited.
+        let arguments = call_expression.params.iter()
+            .map(Rc::as_ptr)
+            .filter(|argument| !self.argument_depths.contains_key(argument))
+            .collect::<Vec<*const ASTNode>>();
+        for argument in &arguments {
+            self.argument_depths.insert(*argument, depth);
+        }
+        let expanded = self.expand_node(&expansion, depth + 1, template);
+        for argument in &arguments {
+            self.argument_depths.remove(argument);
+        }
+
+        expanded
+    }
+
+    fn expand_call(&mut self, definition: &Macro, call_expression: &CallExpression) -> Result<Rc<ASTNode>, ExpandError> {
+        let argc = call_expression.params.len();
+        let arity = Arity { min: definition.params.len(), max: definition.rest.is_none().then_some(definition.params.len()) };
+        if !arity.accepts(argc) {
+            return Err(ExpandError::ArityMismatch(call_expression.value.clone(), arity, argc, call_expression.callee_span.start));
+        }
+
+        let mut bindings = HashMap::new();
+        for (param, argument) in definition.params.iter().zip(&call_expression.params) {
+            bindings.insert(param.as_str(), Binding::One(argument.clone()));
+        }
+        if let Some(rest) = &definition.rest {
+            bindings.insert(rest.as_str(), Binding::Rest(call_expression.params[definition.params.len()..].to_vec()));
+        }
+
+        let mut instantiation = Instantiation { bindings, site: call_expression.span, callee_site: call_expression.callee_span, symbols: HashMap::new(), gensyms: &mut self.gensyms };
+        instantiation.body(&definition.body)
+    }
+}
+
+/// The parts of a `(defmacro name (params...) body)`, with the span of each parameter.
+pub(crate) struct Definition<'a> {
+    pub(crate) name: &'a Identifier,
+    pub(crate) params: Vec<(String, Span)>,
+    pub(crate) rest: Option<(String, Span)>,
+    pub(crate) body: &'a Rc<ASTNode>,
+}
+
+pub(crate) fn parse_defmacro(defmacro: &CallExpression) -> Result<Definition<'_>, ExpandError> {
+    let malformed = |reason: &'static str| ExpandError::MalformedDefmacro(reason, defmacro.callee_span.start);
+
+    let [name, params, body] = defmacro.params.as_slice() else {
+        return Err(malformed("expected a name, a parameter list and a body"));
+    };
+    let ASTNode::Identifier(name) = name.as_ref() else {
+        return Err(malformed("the name must be a symbol"));
+    };
+    let ASTNode::CallExpression(params) = params.as_ref() else {
+        return Err(malformed("the parameters must be a list"));
+    };
+    if !is_template(body) {
+        return Err(malformed("the body must be a parameter or a quasiquote"));
+    }
+
+    // `(a b)` reads as a call of `a` with the argument `b`, and `()` as a call of the empty name.
+    let mut names = vec![];
+    if !params.value.is_empty() {
+        names.push((params.value.clone(), params.callee_span));
+    }
+    for param in &params.params {
+        let ASTNode::Identifier(param) = param.as_ref() else {
+            return Err(malformed("the parameters must be symbols"));
+        };
+        names.push((param.name.clone(), param.span));
+    }
+    let rest = match names.iter().position(|(name, _)| name == REST) {
+        None => None,
+        Some(index) if index + 2 == names.len() => {
+            let rest = names.pop();
+            names.pop();
+            rest
+        }
+        Some(_) => return Err(malformed("&rest must be followed by exactly one parameter")),
+    };
+
+    Ok(Definition { name, params: names, rest, body })
+}
+
+fn is_template(body: &ASTNode) -> bool {
+    match body {
+        ASTNode::Identifier(_) => true,
+        ASTNode::CallExpression(call_expression) => call_expression.value == QUASIQUOTE && call_expression.params.len() == 1,
+        _ => false,
+    }
+}
+
+/// Deepest nesting of call expressions in `node`.
+pub(crate) fn nesting(node: &ASTNode) -> usize {
+    let mut deepest = 0;
+    let mut pending = vec![(node, 0)];
+    while let Some((node, depth)) = pending.pop() {
+        if let ASTNode::CallExpression(call_expression) = node {
+            deepest = deepest.max(depth + 1);
+            pending.extend(call_expression.params.iter().map(|param| (param.as_ref(), depth + 1)));
+        }
+    }
+
+    deepest
+}
+
+/// One expansion of a macro body at a call `site`.
+/// Nodes from the template take the spans of the call, so later errors point at it;
+/// arguments keep their own spans.
+struct Instantiation<'a> {
+    bindings: HashMap<&'a str, Binding>,
+    site: Span,
+    callee_site: Span,
+    /// Symbols the template introduces, renamed for this expansion.
+    symbols: HashMap<String, String>,
+    gensyms: &'a mut usize,
+}
+
+impl Instantiation<'_> {
+    fn body(&mut self, body: &ASTNode) -> Result<Rc<ASTNode>, ExpandError> {
+        match body {
+            ASTNode::Identifier(identifier) => self.unquote(identifier),
+            ASTNode::CallExpression(quasiquote) => self.template(&quasiquote.params[0]),
+            _ => unreachable!("`define` only accepts parameters and quasiquotes as macro bodies"),
+        }
+    }
+
+    fn template(&mut self, node: &ASTNode) -> Result<Rc<ASTNode>, ExpandError> {
+        let site = self.site;
+        let instantiated = match node {
+            ASTNode::NumberLiteral(number_literal) => ASTNode::NumberLiteral(NumberLiteral { span: site, ..number_literal.clone() }),
+            ASTNode::StringLiteral(string_literal) => ASTNode::StringLiteral(StringLiteral { span: site, ..string_literal.clone() }),
+            ASTNode::Identifier(identifier) => ASTNode::Identifier(Identifier {
+                node_type: ASTNodeType::Identifier,
+                span: site,
+                name: self.gensym(&identifier.name),
+            }),
+            ASTNode::CallExpression(call_expression) => match call_expression.value.as_str() {
+                UNQUOTE => return self.unquote(unquoted(call_expression)?),
+                UNQUOTE_SPLICING => return Err(ExpandError::SpliceOutsideCall(call_expression.callee_span.start)),
+                QUASIQUOTE => return Err(ExpandError::NestedQuasiquote(call_expression.callee_span.start)),
+                _ => {
+                    let mut params = vec![];
+                    for param in &call_expression.params {
+                        match param.as_ref() {
+                            ASTNode::CallExpression(splice) if splice.value == UNQUOTE_SPLICING => params.extend(self.splice(unquoted(splice)?)?),
+                            _ => params.push(self.template(param)?),
+                        }
+                    }
+                    ASTNode::CallExpression(CallExpression { span: site, callee_span: self.callee_site, params, ..call_expression.clone() })
+                }
+            },
+            ASTNode::Program(_) | ASTNode::Root(_) => node.clone(),
+        };
+
+        Ok(Rc::new(instantiated))
+    }
+
+    fn unquote(&self, identifier: &Identifier) -> Result<Rc<ASTNode>, ExpandError> {
+        match self.bindings.get(identifier.name.as_str()) {
+            Some(Binding::One(argument)) => Ok(argument.clone()),
+            Some(Binding::Rest(_)) => Err(ExpandError::NotSpliced(identifier.name.clone(), identifier.span.start)),
+            None => Err(ExpandError::UnboundSymbol(identifier.name.clone(), identifier.span.start)),
+        }
+    }
+
+    fn splice(&self, identifier: &Identifier) -> Result<Vec<Rc<ASTNode>>, ExpandError> {
+        match self.bindings.get(identifier.name.as_str()) {
+            Some(Binding::Rest(arguments)) => Ok(arguments.clone()),
+            Some(Binding::One(_)) => Err(ExpandError::NotARestParameter(identifier.name.clone(), identifier.span.start)),
+            None => Err(ExpandError::UnboundSymbol(identifier.name.clone(), identifier.span.start)),
+        }
+    }
+
+    /// Renames a symbol the template introduces, the same way within one expansion.
+    /// `#` cannot appear in source names, so the result never means a symbol at the call site.
+    fn gensym(&mut self, name: &str) -> String {
+        if let Some(renamed) = self.symbols.get(name) {
+            return renamed.clone();
+        }
+
+        *self.gensyms += 1;
+        let renamed = format!("{}#{}", name, self.gensyms);
+        self.symbols.insert(name.to_string(), renamed.clone());
+        renamed
+    }
+}
+
+/// The parameter in `,x` or `,@x`.
+fn unquoted(call_expression: &CallExpression) -> Result<&Identifier, ExpandError> {
+    match call_expression.params.as_slice() {
+        [param] => match param.as_ref() {
+            ASTNode::Identifier(identifier) => Ok(identifier),
+            _ => Err(ExpandError::InvalidUnquote(call_expression.callee_span.start)),
+        },
+        _ => Err(ExpandError::InvalidUnquote(call_expression.callee_span.start)),
+    }
+}
+
+/// Prints `program` as code the parser reads back, one top-level node per line.
+pub fn to_source(program: &Program) -> String {
+    program.body.iter()
+        .map(|node| {
+            let mut source = String::new();
+            write_source(node, &mut source);
+            source
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn write_source(node: &ASTNode, source: &mut String) {
+    match node {
+        ASTNode::NumberLiteral(number_literal) => source.push_str(&number_literal.value),
+        ASTNode::StringLiteral(string_literal) => {
+            // A string ends at either quote, so it contains at most one kind of them.
+            let quote = if string_literal.value.contains('\'') { '"' } else { '\'' };
+            source.push(quote);
+            source.push_str(&string_literal.value);
+            source.push(quote);
+        }
+        ASTNode::Identifier(identifier) => source.push_str(&identifier.name),
+        ASTNode::CallExpression(call_expression) => {
+            source.push('(');
+            source.push_str(&call_expression.value);
+            for param in &call_expression.params {
+                source.push(' ');
+                write_source(param, source);
+            }
+            source.push(')');
+        }
+        ASTNode::Program(program) => source.push_str(&to_source(program)),
+        ASTNode::Root(_) => {}
+    }
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum ExpandError {
+    #[error("Error: Malformed defmacro: {0} at {1}")]
+    MalformedDefmacro(&'static str, Position),
+    #[error("Error: () is only allowed as the parameter list of a defmacro at {0}")]
+    EmptyList(Position),
+    #[error("Error: {0} is only allowed at the top level of a module at {1}")]
+    NotAtTopLevel(String, Position),
+    #[error("Error: {0} is only allowed in the body of a macro at {1}")]
+    QuoteOutsideMacro(String, Position),
+    #[error("Error: Nested quasiquotes are not supported at {0}")]
+    NestedQuasiquote(Position),
+    #[error("Error: Only macro parameters can be unquoted at {0}")]
+    InvalidUnquote(Position),
+    #[error("Error: Rest parameter {0} must be unquoted with ,@ at {1}")]
+    NotSpliced(String, Position),
+    #[error("Error: Only a rest parameter can be spliced, not {0} at {1}")]
+    NotARestParameter(String, Position),
+    #[error("Error: ,@ is only allowed among the arguments of a call at {0}")]
+    SpliceOutsideCall(Position),
+    #[error("Error: {0} expects {1} arguments but got {2} at {3}")]
+    ArityMismatch(String, Arity, usize, Position),
+    #[error("Error: Expansion of {0} is more than {1} macro calls deep at {2}")]
+    DepthExceeded(String, usize, Position),
+    #[error("Error: Unbound symbol: {0} at {1}")]
+    UnboundSymbol(String, Position),
+    #[error("Error: Nesting too deep: more than {0} levels of call expressions")]
+    NestingTooDeep(usize),
+    #[error(transparent)]
+    Resolve(#[from] Diagnostic),
+}
+
+#[cfg(test)]
+mod expander_tests {
+    use crate::expander::{expand, to_source, ExpandError, Expander, DEFAULT_MAX_EXPANSION_DEPTH};
+    use crate::parser::parser;
+    use crate::span::Position;
+    use crate::tokenizer::tokenizer;
+
+    fn expand_code(code: &str) -> Result<String, ExpandError> {
+        expand(parser(tokenizer(code.to_string()).unwrap()).unwrap()).map(|program| to_source(&program))
+    }
+
+    #[test]
+    fn test_expand() {
+        let code = "
+            (defmacro double (x) `(add ,x ,x))
+            (defmacro unless (condition then otherwise) `(if ,condition ,otherwise ,then))
+            (defmacro say (&rest words) `(print 'said:' ,@words))
+            (print (double (subtract 4 2)))
+            (unless (lt 1 2) 'no' (double 3))
+            (say 'hoge' (double 1))
+            (say)
+        ";
+
+        assert_eq!(expand_code(code).unwrap(), concat!(
+            "(print (add (subtract 4 2) (subtract 4 2)))\n",
+            "(if (lt 1 2) (add 3 3) 'no')\n",
+            "(print 'said:' 'hoge' (add 1 1))\n",
+            "(print 'said:')",
+        ));
+    }
+
+    #[test]
+    fn test_expand_points_at_call_site() {
+        let program = expand(parser(tokenizer("(defmacro two () `(add 1 1))\n(print (two))".to_string()).unwrap()).unwrap()).unwrap();
+
+        assert_eq!(to_source(&program), "(print (add 1 1))");
+        let crate::ast::ASTNode::CallExpression(print) = program.body[0].as_ref() else { panic!("not a call") };
+        assert_eq!(print.params[0].get_span().start, Position { offset: 36, line: 2, column: 7 });
+    }
+
+    #[test]
+    fn test_expand_renames_introduced_symbols() {
+        let result_error = expand_code("(defmacro leak (x) `(print tmp ,x tmp)) (leak tmp)").unwrap_err();
+
+        // The template's `tmp` is renamed, so it cannot capture the `tmp` passed in.
+        assert_eq!(result_error, ExpandError::UnboundSymbol("tmp#1".to_string(), Position { offset: 40, line: 1, column: 40 }));
+        assert_eq!(expand_code("(print tmp)").unwrap_err().to_string(), "Error: Unbound symbol: tmp at 1:8");
+    }
+
+    #[test]
+    fn test_expand_depth_limit() {
+        let mut expander = Expander::with_max_depth(10);
+        let program = parser(tokenizer("(defmacro forever (x) `(forever ,x)) (forever 1)".to_string()).unwrap()).unwrap();
+
+        let result_error = expander.expand(program).unwrap_err();
+
+        assert_eq!(result_error.to_string(), "Error: Expansion of forever is more than 10 macro calls deep at 1:39");
+    }
+
+    #[test]
+    fn test_expand_nested_calls_past_depth_limit() {
+        let nested = |callee: &str| format!("{}1{}", format!("({} ", callee).repeat(DEFAULT_MAX_EXPANSION_DEPTH + 50), ")".repeat(DEFAULT_MAX_EXPANSION_DEPTH + 50));
+        let code = format!("(defmacro id (x) x) (defmacro wrap (x) `(print ,x)) {} {}", nested("id"), nested("wrap"));
+
+        let source = expand_code(&code).unwrap();
+
+        assert_eq!(source, format!("1\n{}", nested("print")));
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        let error = |code: &str| expand_code(code).unwrap_err().to_string();
+
+        assert_eq!(error("(defmacro double (x) `(add ,x ,x)) (double 1 2)"), "Error: double expects 1 arguments but got 2 at 1:37");
+        assert_eq!(error("(defmacro double (x) (add x x))"), "Error: Malformed defmacro: the body must be a parameter or a quasiquote at 1:2");
+        assert_eq!(error("(print (defmacro m (x) x))"), "Error: defmacro is only allowed at the top level of a module at 1:9");
+        assert_eq!(error("(print ,x)"), "Error: unquote is only allowed in the body of a macro at 1:8");
+        assert_eq!(error("(print ())"), "Error: () is only allowed as the parameter list of a defmacro at 1:8");
+        assert_eq!(error("(defmacro m () `(print ()))"), "Error: () is only allowed as the parameter list of a defmacro at 1:24");
+        assert_eq!(error("(defmacro m (&rest xs) `(print ,xs)) (m 1)"), "Error: Rest parameter xs must be unquoted with ,@ at 1:33");
+        assert_eq!(error("(defmacro m (x) `(print ,@x)) (m 1)"), "Error: Only a rest parameter can be spliced, not x at 1:27");
+    }
+
+    #[test]
+    fn test_expander_keeps_macros() {
+        let mut expander = Expander::new();
+        let parse = |code: &str| parser(tokenizer(code.to_string()).unwrap()).unwrap();
+
+        expander.expand(parse("(defmacro double (x) `(add ,x ,x))")).unwrap();
+        let program = expander.expand(parse("(double 2)")).unwrap();
+        let result_error = expander.expand(parse("(defmacro double (x) `(multiply 2 ,x))")).unwrap_err();
+
+        assert_eq!(to_source(&program), "(add 2 2)");
+        assert_eq!(result_error.to_string(), "Error: double is already defined at 1:11, again at 1:11");
+    }
+}
@@ -0,0 +1,247 @@
+use std::rc::Rc;
+use crate::ast::{ASTNode, CallExpression, CallExpressionWithCallee, ExpressionStatement, Identifier, NewASTNode, NewProgram, NumberLiteral, Program, StringLiteral};
+
+/// Rewriter over the source `ASTNode` tree.
+/// Each method takes the node by value and returns the nodes that replace it:
+/// an empty `Vec` removes the node, several nodes are spliced into the parent.
+/// The default implementations rebuild the children and keep the node itself.
+pub trait Fold {
+    fn fold_program(&mut self, program: Program) -> Program {
+        rebuild_program(self, program)
+    }
+
+    fn fold_node(&mut self, node: ASTNode) -> Vec<ASTNode> {
+        match node {
+            ASTNode::CallExpression(call_expression) => self.fold_call_expression(call_expression),
+            ASTNode::NumberLiteral(number_literal) => self.fold_number_literal(number_literal),
+            ASTNode::StringLiteral(string_literal) => self.fold_string_literal(string_literal),
+            ASTNode::Identifier(identifier) => self.fold_identifier(identifier),
+            ASTNode::Program(program) => vec![ASTNode::Program(self.fold_program(program))],
+            root @ ASTNode::Root(_) => vec![root],
+        }
+    }
+
+    fn fold_call_expression(&mut self, call_expression: CallExpression) -> Vec<ASTNode> {
+        vec![ASTNode::CallExpression(rebuild_call_expression(self, call_expression))]
+    }
+
+    fn fold_number_literal(&mut self, number_literal: NumberLiteral) -> Vec<ASTNode> {
+        vec![ASTNode::NumberLiteral(number_literal)]
+    }
+
+    fn fold_string_literal(&mut self, string_literal: StringLiteral) -> Vec<ASTNode> {
+        vec![ASTNode::StringLiteral(string_literal)]
+    }
+
+    fn fold_identifier(&mut self, identifier: Identifier) -> Vec<ASTNode> {
+        vec![ASTNode::Identifier(identifier)]
+    }
+}
+
+pub fn rebuild_program<F: Fold + ?Sized>(folder: &mut F, program: Program) -> Program {
+    Program {
+        node_type: program.node_type,
+        span: program.span,
+        body: fold_children(folder, program.body),
+    }
+}
+
+pub fn rebuild_call_expression<F: Fold + ?Sized>(folder: &mut F, call_expression: CallExpression) -> CallExpression {
+    CallExpression {
+        node_type: call_expression.node_type,
+        span: call_expression.span,
+        value: call_expression.value,
+        callee_span: call_expression.callee_span,
+        params: fold_children(folder, call_expression.params),
+    }
+}
+
+fn fold_children<F: Fold + ?Sized>(folder: &mut F, children: Vec<Rc<ASTNode>>) -> Vec<Rc<ASTNode>> {
+    children
+        .into_iter()
+        .flat_map(|child| folder.fold_node(unwrap_or_clone(child)))
+        .map(Rc::new)
+        .collect()
+}
+
+/// Takes the node out of `node`, cloning it only when it is shared.
+pub(crate) fn unwrap_or_clone(node: Rc<ASTNode>) -> ASTNode {
+    Rc::try_unwrap(node).unwrap_or_else(|node| node.as_ref().clone())
+}
+
+/// Rewriter over the transformed `NewASTNode` tree, with the same contract as `Fold`.
+pub trait NewFold {
+    fn fold_new_program(&mut self, new_program: NewProgram) -> NewProgram {
+        rebuild_new_program(self, new_program)
+    }
+
+    fn fold_new_node(&mut self, node: NewASTNode) -> Vec<NewASTNode> {
+        match node {
+            NewASTNode::NewProgram(new_program) => vec![NewASTNode::NewProgram(self.fold_new_program(new_program))],
+            NewASTNode::ExpressionStatement(expression_statement) => self.fold_expression_statement(expression_statement),
+            NewASTNode::CallExpressionWithCallee(call_expression) => self.fold_call_expression_with_callee(call_expression),
+            NewASTNode::Identifier(identifier) => self.fold_identifier(identifier),
+            NewASTNode::NumberLiteral(number_literal) => self.fold_number_literal(number_literal),
+            NewASTNode::StringLiteral(string_literal) => self.fold_string_literal(string_literal),
+        }
+    }
+
+    /// The default drops the statement when its expression is folded away,
+    /// and wraps each expression in its own statement when it is folded into several.
+    fn fold_expression_statement(&mut self, expression_statement: ExpressionStatement) -> Vec<NewASTNode> {
+        let node_type = expression_statement.node_type;
+        let span = expression_statement.span;
+
+        self.fold_new_node(*expression_statement.expression)
+            .into_iter()
+            .map(|expression| NewASTNode::ExpressionStatement(ExpressionStatement {
+                node_type,
+                span,
+                expression: Box::new(expression),
+            }))
+            .collect()
+    }
+
+    fn fold_call_expression_with_callee(&mut self, call_expression: CallExpressionWithCallee) -> Vec<NewASTNode> {
+        vec![NewASTNode::CallExpressionWithCallee(rebuild_call_expression_with_callee(self, call_expression))]
+    }
+
+    fn fold_identifier(&mut self, identifier: Identifier) -> Vec<NewASTNode> {
+        vec![NewASTNode::Identifier(identifier)]
+    }
+
+    fn fold_number_literal(&mut self, number_literal: NumberLiteral) -> Vec<NewASTNode> {
+        vec![NewASTNode::NumberLiteral(number_literal)]
+    }
+
+    fn fold_string_literal(&mut self, string_literal: StringLiteral) -> Vec<NewASTNode> {
+        vec![NewASTNode::StringLiteral(string_literal)]
+    }
+}
+
+pub fn rebuild_new_program<F: NewFold + ?Sized>(folder: &mut F, new_program: NewProgram) -> NewProgram {
+    NewProgram {
+        node_type: new_program.node_type,
+        span: new_program.span,
+        body: new_program.body.into_iter().flat_map(|node| folder.fold_new_node(node)).collect(),
+    }
+}
+
+pub fn rebuild_call_expression_with_callee<F: NewFold + ?Sized>(folder: &mut F, call_expression: CallExpressionWithCallee) -> CallExpressionWithCallee {
+    CallExpressionWithCallee {
+        node_type: call_expression.node_type,
+        span: call_expression.span,
+        callee: call_expression.callee,
+        arguments: call_expression.arguments.into_iter().flat_map(|node| folder.fold_new_node(node)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod fold_tests {
+    use crate::span::Span;
+    use std::rc::Rc;
+    use crate::ast::{ASTNode, ASTNodeType, CallExpression, CallExpressionWithCallee, ExpressionStatement, Identifier, NewASTNode, NewProgram, NumberLiteral, StringLiteral};
+    use crate::fold::{rebuild_call_expression, unwrap_or_clone, Fold, NewFold};
+    use crate::test_support::ast::{call, number, program, string};
+
+    /// (subtract x 0) => x
+    struct SubtractZero;
+
+    impl Fold for SubtractZero {
+        fn fold_call_expression(&mut self, call_expression: CallExpression) -> Vec<ASTNode> {
+            let call_expression = rebuild_call_expression(self, call_expression);
+            let is_subtract_zero = call_expression.value == "subtract"
+                && call_expression.params.len() == 2
+                && matches!(call_expression.params[1].as_ref(), ASTNode::NumberLiteral(n) if n.value == "0");
+
+            if is_subtract_zero {
+                let mut params = call_expression.params;
+                params.truncate(1);
+                return params.into_iter().map(unwrap_or_clone).collect();
+            }
+
+            vec![ASTNode::CallExpression(call_expression)]
+        }
+    }
+
+    struct RemoveStrings;
+
+    impl Fold for RemoveStrings {
+        fn fold_string_literal(&mut self, _string_literal: StringLiteral) -> Vec<ASTNode> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_fold_replaces_nested_node() {
+        let source = program(vec![call("add", vec![number("2"), call("subtract", vec![call("subtract", vec![number("4"), number("0")]), number("0")])])]);
+
+        let result_program = SubtractZero.fold_program(source);
+
+        let expected_program = program(vec![call("add", vec![number("2"), number("4")])]);
+
+        assert_eq!(result_program, expected_program);
+    }
+
+    #[test]
+    fn test_fold_removes_node() {
+        let hoge = string("hoge");
+        let source = program(vec![call("fullName", vec![hoge.clone(), number("1"), hoge]), number("2")]);
+
+        let result_program = RemoveStrings.fold_program(source);
+
+        let expected_program = program(vec![call("fullName", vec![number("1")]), number("2")]);
+
+        assert_eq!(result_program, expected_program);
+    }
+
+    /// Splits `(pair a b)` statements into one statement per argument.
+    struct SplitPair;
+
+    impl NewFold for SplitPair {
+        fn fold_call_expression_with_callee(&mut self, call_expression: CallExpressionWithCallee) -> Vec<NewASTNode> {
+            if call_expression.callee.name == "pair" {
+                return call_expression.arguments;
+            }
+
+            vec![NewASTNode::CallExpressionWithCallee(call_expression)]
+        }
+    }
+
+    #[test]
+    fn test_new_fold_splits_statement() {
+        let new_program = NewProgram {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                NewASTNode::ExpressionStatement(ExpressionStatement {
+                    node_type: ASTNodeType::ExpressionStatement,
+                    span: Span::default(),
+                    expression: Box::new(NewASTNode::CallExpressionWithCallee(CallExpressionWithCallee {
+                        node_type: ASTNodeType::CallExpression,
+                        span: Span::default(),
+                        callee: Identifier { node_type: ASTNodeType::Identifier, span: Span::default(), name: "pair".to_string() },
+                        arguments: vec![
+                            NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "1".to_string() }),
+                            NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "2".to_string() }),
+                        ],
+                    })),
+                }),
+            ],
+        };
+
+        let result_program = SplitPair.fold_new_program(new_program);
+
+        let values = result_program.body.iter()
+            .map(|statement| match statement {
+                NewASTNode::ExpressionStatement(ExpressionStatement { expression, .. }) => match expression.as_ref() {
+                    NewASTNode::NumberLiteral(n) => n.value.clone(),
+                    _ => panic!("unexpected expression"),
+                },
+                _ => panic!("unexpected statement"),
+            })
+            .collect::<Vec<String>>();
+
+        assert_eq!(values, vec!["1".to_string(), "2".to_string()]);
+    }
+}
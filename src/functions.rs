@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::eval::IF;
+use crate::types::{signature, Type};
+
+/// The functions every target provides, and `if`.
+const BUILTINS: [&str; 12] = ["add", "subtract", "multiply", "divide", "concat", "print", "eq", "lt", "le", "gt", "ge", IF];
+
+/// How many arguments a function takes; `max` is `None` for variadic functions.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exactly(count: usize) -> Arity {
+        Arity { min: count, max: Some(count) }
+    }
+
+    pub fn accepts(&self, argc: usize) -> bool {
+        argc >= self.min && self.max.map_or(true, |max| argc <= max)
+    }
+}
+
+/// Formats to fit "expects {} arguments".
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
+/// A function programs may call.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub arity: Arity,
+    /// Parameter and result types for the type checker; calls to functions without one are not type checked.
+    pub signature: Option<Type>,
+    /// Name to call in the code of a target, when it differs from `name`.
+    target_names: HashMap<String, String>,
+}
+
+impl Function {
+    pub fn new(name: &str, arity: Arity) -> Function {
+        Function { name: name.to_string(), arity, signature: None, target_names: HashMap::new() }
+    }
+
+    /// Also sets the arity the signature implies.
+    pub fn with_signature(mut self, signature: Type) -> Function {
+        if let Type::Function { params, rest, .. } = &signature {
+            self.arity = Arity { min: params.len(), max: rest.is_none().then_some(params.len()) };
+        }
+        self.signature = Some(signature);
+
+        self
+    }
+
+    pub fn with_target_name(mut self, target: &str, name: &str) -> Function {
+        self.target_names.insert(target.to_string(), name.to_string());
+
+        self
+    }
+
+    pub fn target_name(&self, target: &str) -> &str {
+        self.target_names.get(target).map_or(&self.name, |name| name)
+    }
+}
+
+/// The functions the transformer and the type checker know.
+/// Calls to other functions are allowed unless `strict` is set.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Function>,
+    pub strict: bool,
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> FunctionRegistry {
+        FunctionRegistry::with_builtins()
+    }
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry { functions: HashMap::new(), strict: false }
+    }
+
+    /// The builtins, with the signatures from `types::signature`.
+    pub fn with_builtins() -> FunctionRegistry {
+        let mut registry = FunctionRegistry::new();
+        for name in BUILTINS {
+            let signature = signature(name).expect("every builtin has a signature");
+            registry.register(Function::new(name, Arity::exactly(0)).with_signature(signature));
+        }
+
+        registry
+    }
+
+    /// Replaces a registered function with the same name.
+    pub fn register(&mut self, function: Function) {
+        self.functions.insert(function.name.clone(), function);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// The registered names, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod functions_tests {
+    use crate::functions::{Arity, Function, FunctionRegistry};
+
+    #[test]
+    fn test_builtin_arities() {
+        let registry = FunctionRegistry::with_builtins();
+
+        assert_eq!(registry.get("add").unwrap().arity, Arity::exactly(2));
+        assert_eq!(registry.get("concat").unwrap().arity, Arity { min: 0, max: None });
+        assert_eq!(registry.get("if").unwrap().arity, Arity::exactly(3));
+        assert_eq!(registry.get("fullName"), None);
+    }
+
+    #[test]
+    fn test_arity() {
+        let arity = Arity { min: 1, max: Some(3) };
+
+        assert!(!arity.accepts(0));
+        assert!(arity.accepts(3));
+        assert!(Arity { min: 1, max: None }.accepts(100));
+        assert_eq!(arity.to_string(), "1 to 3");
+        assert_eq!(Arity { min: 1, max: None }.to_string(), "at least 1");
+        assert_eq!(Arity::exactly(2).to_string(), "2");
+    }
+
+    #[test]
+    fn test_target_name() {
+        let function = Function::new("fullName", Arity::exactly(2)).with_target_name("python", "full_name");
+
+        assert_eq!(function.target_name("python"), "full_name");
+        assert_eq!(function.target_name("js"), "fullName");
+    }
+}
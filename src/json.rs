@@ -0,0 +1,253 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+use crate::ast::{ASTNode, NewASTNode, NewProgram, Program};
+use crate::expander::nesting;
+use crate::parser::{ParseError, DEFAULT_MAX_DEPTH};
+use crate::token::Token;
+
+/// Levels of JSON nesting in a tree of `DEFAULT_MAX_DEPTH` calls: an object and an array per call,
+/// plus the program, statement, leaf node and its span around them.
+const MAX_JSON_DEPTH: usize = 2 * DEFAULT_MAX_DEPTH + 8;
+
+pub fn to_json<T: Serialize + ?Sized>(value: &T) -> anyhow::Result<String> {
+    Ok(serde_json::to_string_pretty(value)?)
+}
+
+pub fn tokens_from_json(json: &str) -> anyhow::Result<Vec<Token>> {
+    from_json(json)
+}
+
+/// Expects a node of type `Program`, as written by `--emit ast`.
+/// Calls nested deeper than the parser allows are rejected with the parser's error.
+pub fn program_from_json(json: &str) -> anyhow::Result<Program> {
+    let program = match from_json(json)? {
+        ASTNode::Program(program) => program,
+        node => return Err(JsonError::NotAProgram(node.get_node_type().to_string()).into()),
+    };
+    if program.body.iter().map(|node| nesting(node)).max().unwrap_or(0) > DEFAULT_MAX_DEPTH {
+        return Err(ParseError::NestingTooDeep(DEFAULT_MAX_DEPTH).into());
+    }
+
+    Ok(program)
+}
+
+/// Expects a node of type `Program`, as written by `--emit new-ast`.
+/// Calls nested deeper than the parser allows are rejected with the parser's error.
+pub fn new_program_from_json(json: &str) -> anyhow::Result<NewProgram> {
+    let new_program = match from_json(json)? {
+        NewASTNode::NewProgram(new_program) => new_program,
+        node => return Err(JsonError::NotAProgram(node.get_node_type().to_string()).into()),
+    };
+    if new_program.body.iter().map(new_nesting).max().unwrap_or(0) > DEFAULT_MAX_DEPTH {
+        return Err(ParseError::NestingTooDeep(DEFAULT_MAX_DEPTH).into());
+    }
+
+    Ok(new_program)
+}
+
+/// Like `serde_json::from_str` without its recursion limit of 128, growing the stack instead,
+/// so every tree the parser accepts can be read back.
+/// Anything deeper than such a tree is rejected before it is deserialized.
+fn from_json<T: DeserializeOwned>(json: &str) -> anyhow::Result<T> {
+    if json_depth(json) > MAX_JSON_DEPTH {
+        return Err(ParseError::NestingTooDeep(DEFAULT_MAX_DEPTH).into());
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    deserializer.disable_recursion_limit();
+    let value = T::deserialize(serde_stacker::Deserializer::new(&mut deserializer))?;
+    deserializer.end()?;
+
+    Ok(value)
+}
+
+/// The deepest nesting of objects and arrays in `json`, ignoring brackets inside strings.
+/// Malformed input is left for serde_json to report.
+fn json_depth(json: &str) -> usize {
+    let mut depth: usize = 0;
+    let mut deepest = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for byte in json.bytes() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    deepest
+}
+
+/// How many call expressions are nested in `node`, counting it.
+fn new_nesting(node: &NewASTNode) -> usize {
+    let mut deepest = 0;
+    let mut pending = vec![(node, 0)];
+    while let Some((node, depth)) = pending.pop() {
+        match node {
+            NewASTNode::ExpressionStatement(expression_statement) => pending.push((&expression_statement.expression, depth)),
+            NewASTNode::CallExpressionWithCallee(call_expression) => {
+                deepest = deepest.max(depth + 1);
+                pending.extend(call_expression.arguments.iter().map(|argument| (argument, depth + 1)));
+            }
+            _ => {}
+        }
+    }
+
+    deepest
+}
+
+#[derive(Debug, Error)]
+pub enum JsonError {
+    #[error("Error: Expected a Program node but found: {0}")]
+    NotAProgram(String),
+}
+
+#[cfg(test)]
+mod json_tests {
+    use crate::span::Span;
+    use std::rc::Rc;
+    use crate::ast::{ASTNode, ASTNodeType, CallExpression, CallExpressionWithCallee, ExpressionStatement, Identifier, NewASTNode, NewProgram, NumberLiteral, Program, StringLiteral};
+    use crate::json::{json_depth, new_program_from_json, program_from_json, to_json, tokens_from_json};
+    use crate::parser::DEFAULT_MAX_DEPTH;
+    use crate::token::{Token, TokenType};
+
+    fn nested_new_program_json(depth: usize) -> String {
+        format!(
+            r#"{{ "type": "Program", "body": [{{ "type": "ExpressionStatement", "expression": {}{{ "type": "NumberLiteral", "value": "1" }}{} }}] }}"#,
+            r#"{ "type": "CallExpression", "callee": { "name": "print" }, "arguments": ["#.repeat(depth),
+            "] }".repeat(depth),
+        )
+    }
+
+    #[test]
+    fn test_tokens_round_trip() {
+        let tokens = vec![
+            Token { token_type: TokenType::PAREN, value: "(".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NAME, value: "add".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NUMBER, value: "2".to_string(), span: Span::default() },
+            Token { token_type: TokenType::STRING, value: "hoge".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: ")".to_string(), span: Span::default() },
+        ];
+
+        let json = to_json(&tokens).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value[0], serde_json::json!({ "type": "paren", "value": "(" }));
+        assert_eq!(value[1], serde_json::json!({ "type": "name", "value": "add" }));
+        assert_eq!(tokens_from_json(&json).unwrap(), tokens);
+    }
+
+    #[test]
+    fn test_program_round_trip() {
+        let program = Program {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                Rc::new(ASTNode::CallExpression(CallExpression {
+                    node_type: ASTNodeType::CallExpression,
+                    span: Span::default(),
+                    value: "add".to_string(),
+                    callee_span: Span::default(),
+                    params: vec![
+                        Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "2".to_string() })),
+                        Rc::new(ASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "hoge".to_string() })),
+                    ],
+                })),
+            ],
+        };
+
+        let json = to_json(&ASTNode::Program(program.clone())).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value, serde_json::json!({
+            "type": "Program",
+            "body": [{
+                "type": "CallExpression",
+                "value": "add",
+                "params": [
+                    { "type": "NumberLiteral", "value": "2" },
+                    { "type": "StringLiteral", "value": "hoge" },
+                ],
+            }],
+        }));
+        assert_eq!(program_from_json(&json).unwrap(), program);
+    }
+
+    #[test]
+    fn test_new_program_round_trip() {
+        let new_program = NewProgram {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                NewASTNode::ExpressionStatement(ExpressionStatement {
+                    node_type: ASTNodeType::ExpressionStatement,
+                    span: Span::default(),
+                    expression: Box::new(NewASTNode::CallExpressionWithCallee(CallExpressionWithCallee {
+                        node_type: ASTNodeType::CallExpression,
+                        span: Span::default(),
+                        callee: Identifier { node_type: ASTNodeType::Identifier, span: Span::default(), name: "add".to_string() },
+                        arguments: vec![
+                            NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "2".to_string() }),
+                        ],
+                    })),
+                }),
+            ],
+        };
+
+        let json = to_json(&NewASTNode::NewProgram(new_program)).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value, serde_json::json!({
+            "type": "Program",
+            "body": [{
+                "type": "ExpressionStatement",
+                "expression": {
+                    "type": "CallExpression",
+                    "callee": { "name": "add" },
+                    "arguments": [{ "type": "NumberLiteral", "value": "2" }],
+                },
+            }],
+        }));
+
+        let result_program = new_program_from_json(&json).unwrap();
+        assert_eq!(to_json(&NewASTNode::NewProgram(result_program)).unwrap(), json);
+    }
+
+    #[test]
+    fn test_program_from_json_rejects_other_nodes() {
+        let result_error = program_from_json(r#"{ "type": "NumberLiteral", "value": "1" }"#).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Expected a Program node but found: NumberLiteral");
+    }
+
+    #[test]
+    fn test_json_depth_ignores_brackets_in_strings() {
+        assert_eq!(json_depth(r#"{ "value": "[{\"[" }"#), 1);
+        assert_eq!(json_depth(r#"[[1], [[2]]]"#), 3);
+    }
+
+    #[test]
+    fn test_new_program_from_json_rejects_deep_nesting() {
+        let result_error = new_program_from_json(&nested_new_program_json(1_000_000)).unwrap_err();
+
+        assert_eq!(
+            format!("{}", result_error.root_cause()),
+            format!("Error: Nesting too deep: more than {} levels of call expressions", DEFAULT_MAX_DEPTH),
+        );
+    }
+}
@@ -0,0 +1,36 @@
+pub mod token;
+pub mod span;
+pub mod tokenizer;
+pub mod compiler;
+pub mod parser;
+pub mod expander;
+pub mod module;
+pub mod resolver;
+pub mod ast;
+pub mod traverser;
+pub mod visitor;
+pub mod transformer;
+pub mod code_generator;
+pub mod source_map;
+pub mod backend;
+pub mod wat;
+pub mod c;
+pub mod python;
+pub mod fold;
+pub mod optimizer;
+pub mod eval;
+pub mod types;
+pub mod functions;
+pub mod suggest;
+pub mod bytecode;
+pub mod vm;
+pub mod arena;
+pub mod dot;
+pub mod cli;
+pub mod repl;
+#[cfg(feature = "serde")]
+pub mod json;
+#[cfg(feature = "serde")]
+pub mod estree;
+#[cfg(test)]
+mod test_support;
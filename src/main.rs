@@ -1,17 +1,28 @@
-use crate::compiler::compiler;
-// use crate::visitor::tra_test;
-
-mod token;
-mod tokenizer;
-mod compiler;
-mod parser;
-mod ast;
-mod traverser;
-mod visitor;
-mod transformer;
-mod code_generator;
+use std::io::Read;
+use the_super_tiny_compiler_rust::cli::{parse_args, parse_repl_args, run};
+use the_super_tiny_compiler_rust::repl::{self, History};
 
 fn main() -> anyhow::Result<()> {
-    let input= "(add 1 2)".to_string();
-    compiler(input)
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("repl") {
+        let history = parse_repl_args(args.skip(1))?;
+        repl::start(std::io::stdin().lock(), &mut std::io::stdout(), History::load(Some(history)))?;
+        return Ok(());
+    }
+
+    let options = parse_args(args)?;
+
+    let input = match &options.input {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            input
+        }
+    };
+
+    let output = run(&options, input)?;
+    println!("{}", output);
+
+    Ok(())
 }
@@ -0,0 +1,285 @@
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use thiserror::Error;
+use crate::ast::{ASTNode, ASTNodeType, CallExpression, Program};
+use crate::expander::Expander;
+use crate::parser::parser;
+use crate::resolver::Diagnostic;
+use crate::span::{Position, Span};
+use crate::tokenizer::tokenizer;
+
+pub const IMPORT: &str = "import";
+pub const EXPORT: &str = "export";
+
+/// One file of a program, after macro expansion.
+#[derive(Debug)]
+pub struct Module {
+    /// Canonical path of the file, or the path given for an entry module that is not a file.
+    pub path: PathBuf,
+    /// Path relative to the directory of the entry module, or the file name for modules outside it.
+    pub name: String,
+    /// The code of the module, without its `import`s and `export`s.
+    pub program: Program,
+    /// Warnings about the code of the module, such as macros shadowing builtins.
+    pub warnings: Vec<Diagnostic>,
+    /// Holds the macros of the module, for modules importing it.
+    expander: Expander,
+    exports: Vec<String>,
+}
+
+/// Reads the module at `path` and loads the modules it imports; see `load_program`.
+pub fn load(path: &Path) -> anyhow::Result<Vec<Module>> {
+    let code = std::fs::read_to_string(path)?;
+
+    load_program(path, parser(tokenizer(code)?)?)
+}
+
+/// Loads the modules `program`, read from `path`, imports, each once, and expands the macros of all of them.
+/// `(import "file.lisp")` is resolved relative to the directory of the importing module, and brings in the
+/// macros the file lists with `(export name...)`. `path` need not exist, as for stdin.
+/// Returns the modules in dependency order: each after those it imports, `program` last.
+pub fn load_program(path: &Path, program: Program) -> anyhow::Result<Vec<Module>> {
+    let mut loader = Loader::new(&directory(path));
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    let mut expander = Expander::new();
+    let (program, exports) = loader.link(&path, program, &mut expander)?;
+    loader.push(path, program, expander, exports);
+    Ok(loader.modules)
+}
+
+/// The code of `modules` as one program, in their order.
+pub fn bundle(modules: Vec<Module>) -> Program {
+    let span = modules.last().map_or(Span::default(), |module| module.program.span);
+
+    Program {
+        node_type: ASTNodeType::Program,
+        span,
+        body: modules.into_iter().flat_map(|module| module.program.body).collect(),
+    }
+}
+
+fn directory(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Loads modules once each, for programs that come in parts, like the forms of a REPL.
+pub struct Loader {
+    /// Directory of the entry module, which module names are relative to.
+    root: PathBuf,
+    /// Modules loaded so far, each after those it imports.
+    modules: Vec<Module>,
+    /// Paths of the modules being loaded, importers first.
+    loading: Vec<PathBuf>,
+}
+
+impl Loader {
+    /// Names modules relative to `root`.
+    pub fn new(root: &Path) -> Loader {
+        Loader { root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()), modules: vec![], loading: vec![] }
+    }
+
+    /// Loads the modules `program`, read from `path`, imports, unless they already are, and expands it
+    /// with `expander`, which gets the macros they export. Returns the code of the modules loaded now,
+    /// each after those it imports, then `program`.
+    pub fn load(&mut self, expander: &mut Expander, path: &Path, program: Program) -> anyhow::Result<Program> {
+        let loaded = self.modules.len();
+        // Modules loaded before an error stay loaded, as `expander` may have their macros already.
+        let (program, _) = self.link(path, program, expander).map_err(|error| {
+            self.loading.clear();
+            error
+        })?;
+
+        let mut body = self.modules[loaded..].iter().flat_map(|module| module.program.body.clone()).collect::<Vec<_>>();
+        body.extend(program.body);
+        Ok(Program { node_type: program.node_type, span: program.span, body })
+    }
+
+    /// Loads the imports of `program`, read from `path`, then expands it with `expander` and the macros they export.
+    /// Returns the expanded program and the names it exports.
+    fn link(&mut self, path: &Path, program: Program, expander: &mut Expander) -> anyhow::Result<(Program, Vec<String>)> {
+        self.loading.push(path.to_path_buf());
+
+        let mut exports = vec![];
+        let mut body = vec![];
+        for node in &program.body {
+            match node.as_ref() {
+                ASTNode::CallExpression(call_expression) if call_expression.value == IMPORT => {
+                    let imported = self.import(path, call_expression)?;
+                    let imported = &self.modules[imported];
+                    for name in &imported.exports {
+                        expander.import_macro(&imported.expander, name).with_context(|| format!("Error: Importing {}", imported.name))?;
+                    }
+                }
+                ASTNode::CallExpression(call_expression) if call_expression.value == EXPORT => {
+                    for param in &call_expression.params {
+                        let ASTNode::Identifier(identifier) = param.as_ref() else {
+                            return Err(ModuleError::MalformedExport(call_expression.callee_span.start).into());
+                        };
+                        exports.push((identifier.name.clone(), identifier.span.start));
+                    }
+                }
+                _ => body.push(node.clone()),
+            }
+        }
+
+        let program = expander.expand(Program { node_type: program.node_type, span: program.span, body })?;
+        if let Some((name, position)) = exports.iter().find(|(name, _)| !expander.defines(name)) {
+            return Err(ModuleError::UnknownExport(name.clone(), *position).into());
+        }
+
+        self.loading.pop();
+        Ok((program, exports.into_iter().map(|(name, _)| name).collect()))
+    }
+
+    /// Adds a linked module; returns its index in `modules`.
+    fn push(&mut self, path: PathBuf, program: Program, mut expander: Expander, exports: Vec<String>) -> usize {
+        self.modules.push(Module {
+            name: self.name(&path),
+            path,
+            program,
+            warnings: expander.take_warnings(),
+            expander,
+            exports,
+        });
+        self.modules.len() - 1
+    }
+
+    /// Loads the module `import` names, unless it already is; returns its index in `modules`.
+    fn import(&mut self, importer: &Path, import: &CallExpression) -> anyhow::Result<usize> {
+        let relative = match import.params.as_slice() {
+            [param] => match param.as_ref() {
+                ASTNode::StringLiteral(relative) => relative,
+                _ => return Err(ModuleError::MalformedImport(import.callee_span.start).into()),
+            },
+            _ => return Err(ModuleError::MalformedImport(import.callee_span.start).into()),
+        };
+        let path = directory(importer).join(&relative.value).canonicalize()
+            .map_err(|error| ModuleError::NotFound(relative.value.clone(), error.to_string(), relative.span.start))?;
+
+        if let Some(index) = self.modules.iter().position(|module| module.path == path) {
+            return Ok(index);
+        }
+        if let Some(start) = self.loading.iter().position(|loading| *loading == path) {
+            let cycle = self.loading[start..].iter().chain([&path]).map(|path| self.name(path)).collect::<Vec<String>>();
+            return Err(ModuleError::Cycle(cycle.join(" -> ")).into());
+        }
+
+        let name = self.name(&path);
+        let code = std::fs::read_to_string(&path).with_context(|| format!("Error: In module {}", name))?;
+        let program = tokenizer(code).and_then(parser).with_context(|| format!("Error: In module {}", name))?;
+        let mut expander = Expander::new();
+        let (program, exports) = self.link(&path, program, &mut expander).with_context(|| format!("Error: In module {}", name))?;
+        Ok(self.push(path, program, expander, exports))
+    }
+
+    fn name(&self, path: &Path) -> String {
+        let name = path.strip_prefix(&self.root).ok()
+            .or_else(|| path.file_name().map(Path::new))
+            .unwrap_or(path);
+
+        name.display().to_string()
+    }
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum ModuleError {
+    #[error("Error: Malformed import: expected one path string at {0}")]
+    MalformedImport(Position),
+    #[error("Error: Malformed export: expected macro names at {0}")]
+    MalformedExport(Position),
+    #[error("Error: Cannot import {0}: {1} at {2}")]
+    NotFound(String, String, Position),
+    #[error("Error: Import cycle: {0}")]
+    Cycle(String),
+    #[error("Error: Unknown export: {0} is not a macro of this module at {1}")]
+    UnknownExport(String, Position),
+}
+
+#[cfg(test)]
+mod module_tests {
+    use std::path::PathBuf;
+    use crate::expander::to_source;
+    use crate::module::{bundle, load};
+
+    /// Writes `files` into a new directory named after `test`, and returns it.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("super-tiny-modules-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (name, code) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, code).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_load_in_dependency_order() {
+        let dir = write_files("order", &[
+            ("main.lisp", "(import \"lib/twice.lisp\") (import \"lib/greet.lisp\") (print (twice 2))"),
+            ("lib/twice.lisp", "(defmacro twice (x) `(add ,x ,x)) (export twice)"),
+            ("lib/greet.lisp", "(import \"twice.lisp\") (print (twice 'hi'))"),
+        ]);
+
+        let modules = load(&dir.join("main.lisp")).unwrap();
+
+        let names = modules.iter().map(|module| module.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["lib/twice.lisp", "lib/greet.lisp", "main.lisp"]);
+        assert_eq!(to_source(&bundle(modules)), "(print (add 'hi' 'hi'))\n(print (add 2 2))");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_keeps_macros_private() {
+        let dir = write_files("private", &[
+            ("main.lisp", "(import \"lib.lisp\") (print (public 1) (helper 1))"),
+            ("lib.lisp", "(defmacro helper (x) x) (defmacro public (x) `(helper ,x)) (export public)"),
+        ]);
+
+        let modules = load(&dir.join("main.lisp")).unwrap();
+
+        // `public` can use `helper`, but main.lisp cannot: there it is an ordinary call.
+        assert_eq!(to_source(&modules[1].program), "(print 1 (helper 1))");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_detects_cycles() {
+        let dir = write_files("cycle", &[
+            ("a.lisp", "(import \"b.lisp\")"),
+            ("b.lisp", "(import \"c.lisp\")"),
+            ("c.lisp", "(import \"b.lisp\")"),
+        ]);
+
+        let result_error = load(&dir.join("a.lisp")).unwrap_err();
+
+        assert_eq!(result_error.root_cause().to_string(), "Error: Import cycle: b.lisp -> c.lisp -> b.lisp");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let dir = write_files("errors", &[
+            ("missing.lisp", "(print 1)\n(import \"nope.lisp\")"),
+            ("export.lisp", "(export nope)"),
+            ("broken.lisp", "(import \"export.lisp\")"),
+            ("twice.lisp", "(defmacro twice (x) `(add ,x ,x)) (export twice)"),
+            ("also_twice.lisp", "(defmacro twice (x) `(multiply 2 ,x)) (export twice)"),
+            ("redefined.lisp", "(import \"twice.lisp\")\n(defmacro twice (x) x)"),
+            ("clash.lisp", "(import \"twice.lisp\")\n(import \"also_twice.lisp\")"),
+        ]);
+        let error = |name: &str| format!("{:#}", load(&dir.join(name)).unwrap_err());
+
+        assert!(error("missing.lisp").starts_with("Error: Cannot import nope.lisp: "), "{}", error("missing.lisp"));
+        assert!(error("missing.lisp").ends_with(" at 2:9"), "{}", error("missing.lisp"));
+        assert_eq!(error("export.lisp"), "Error: Unknown export: nope is not a macro of this module at 1:9");
+        assert_eq!(error("broken.lisp"), "Error: In module export.lisp: Error: Unknown export: nope is not a macro of this module at 1:9");
+        assert_eq!(error("redefined.lisp"), "Error: twice is already defined at 1:11, again at 2:11");
+        assert_eq!(error("clash.lisp"), "Error: Importing also_twice.lisp: Error: twice is already defined at 1:11, again at 1:11");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
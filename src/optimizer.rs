@@ -0,0 +1,121 @@
+use strum_macros::{Display, EnumString};
+use crate::ast::{ASTNode, ASTNodeType, CallExpression, NumberLiteral, Program, StringLiteral};
+use crate::fold::{rebuild_call_expression, Fold};
+
+/// How much the optimizer rewrites the source AST before it is transformed.
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy, Default, EnumString, Display)]
+pub enum OptLevel {
+    /// Keep the program as written.
+    #[default]
+    #[strum(serialize = "0")]
+    O0,
+    /// Fold calls of pure builtins on literal arguments.
+    #[strum(serialize = "1")]
+    O1,
+}
+
+pub fn optimize(program: Program, level: OptLevel) -> Program {
+    match level {
+        OptLevel::O0 => program,
+        OptLevel::O1 => ConstantFolder.fold_program(program),
+    }
+}
+
+/// Evaluates `add`, `subtract`, `multiply`, `divide` and `concat` at compile time
+/// when all their arguments are literals, innermost calls first.
+/// Integer overflow, division by zero and inexact division are left for runtime.
+struct ConstantFolder;
+
+impl Fold for ConstantFolder {
+    fn fold_call_expression(&mut self, call_expression: CallExpression) -> Vec<ASTNode> {
+        let call_expression = rebuild_call_expression(self, call_expression);
+
+        match fold_call(&call_expression) {
+            Some(node) => vec![node],
+            None => vec![ASTNode::CallExpression(call_expression)],
+        }
+    }
+}
+
+fn fold_call(call_expression: &CallExpression) -> Option<ASTNode> {
+    let span = call_expression.span;
+
+    if call_expression.value == "concat" {
+        let strings = call_expression.params.iter()
+            .map(|param| match param.as_ref() {
+                ASTNode::StringLiteral(string_literal) => Some(string_literal.value.as_str()),
+                _ => None,
+            })
+            .collect::<Option<Vec<&str>>>()?;
+        if strings.is_empty() {
+            return None;
+        }
+
+        return Some(ASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span, value: strings.concat() }));
+    }
+
+    let operator: fn(i64, i64) -> Option<i64> = match call_expression.value.as_str() {
+        "add" => i64::checked_add,
+        "subtract" => i64::checked_sub,
+        "multiply" => i64::checked_mul,
+        "divide" => |left, right| if right != 0 && left.checked_rem(right)? == 0 { left.checked_div(right) } else { None },
+        _ => return None,
+    };
+
+    let [left, right] = call_expression.params.as_slice() else {
+        return None;
+    };
+    let value = operator(integer(left)?, integer(right)?)?;
+
+    Some(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span, value: value.to_string() }))
+}
+
+fn integer(node: &ASTNode) -> Option<i64> {
+    match node {
+        ASTNode::NumberLiteral(number_literal) => number_literal.value.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod optimizer_tests {
+    use crate::optimizer::{optimize, OptLevel};
+    use crate::test_support::ast::{call, number, program, string};
+
+    #[test]
+    fn test_fold_nested_arithmetic() {
+        let source = program(vec![
+            call("add", vec![number("2"), call("subtract", vec![number("4"), number("2")])]),
+            call("multiply", vec![call("divide", vec![number("9"), number("3")]), number("5")]),
+            call("concat", vec![string("hoge"), string("foo")]),
+        ]);
+
+        let result_program = optimize(source, OptLevel::O1);
+
+        assert_eq!(result_program, program(vec![number("4"), number("15"), string("hogefoo")]));
+    }
+
+    #[test]
+    fn test_keep_calls_that_cannot_be_folded() {
+        let calls = vec![
+            call("add", vec![number("9223372036854775807"), number("1")]),
+            call("divide", vec![number("1"), number("0")]),
+            call("divide", vec![number("7"), number("2")]),
+            call("subtract", vec![number("4"), string("2")]),
+            call("concat", vec![string("hoge"), number("1")]),
+            call("fullName", vec![string("hoge"), string("foo")]),
+        ];
+
+        let result_program = optimize(program(calls.clone()), OptLevel::O1);
+
+        assert_eq!(result_program, program(calls));
+    }
+
+    #[test]
+    fn test_fold_arguments_of_unknown_call() {
+        let source = program(vec![call("print", vec![call("add", vec![number("1"), number("2")])])]);
+
+        assert_eq!(optimize(source.clone(), OptLevel::O0), source);
+        assert_eq!(optimize(source, OptLevel::O1), program(vec![call("print", vec![number("3")])]));
+    }
+}
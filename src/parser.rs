@@ -1,9 +1,19 @@
 use std::rc::Rc;
 use thiserror::Error;
+use crate::span::Span;
 use crate::token::{Token, TokenType};
-use crate::ast::{ASTNode, ASTNodeType, CallExpression, NumberLiteral, StringLiteral, Program};
+use crate::ast::{ASTNode, ASTNodeType, CallExpression, Identifier, NumberLiteral, StringLiteral, Program};
+use crate::expander::{QUASIQUOTE, UNQUOTE, UNQUOTE_SPLICING};
+
+/// Call expressions nested deeper than this are rejected with `ParseError::NestingTooDeep`.
+/// Later passes walk the tree recursively, so this also bounds their stack usage.
+pub const DEFAULT_MAX_DEPTH: usize = 1_000;
 
 pub fn parser(tokens: Vec<Token>) -> anyhow::Result<Program> {
+    parser_with_max_depth(tokens, DEFAULT_MAX_DEPTH)
+}
+
+pub fn parser_with_max_depth(tokens: Vec<Token>, max_depth: usize) -> anyhow::Result<Program> {
     let mut current: usize = 0;
     let tokens_length: usize = tokens.len();
 
@@ -13,20 +23,26 @@ pub fn parser(tokens: Vec<Token>) -> anyhow::Result<Program> {
 
     let mut program = Program {
         node_type: ASTNodeType::Program,
+        span: match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.span.to(last.span),
+            _ => Span::default(),
+        },
         body: vec![],
     };
 
     while is_eos(current) {
-        let ast_node = walk(&mut current, &tokens)?;
+        let ast_node = walk(&mut current, &tokens, max_depth)?;
         program.body.push(Rc::new(ast_node));
     }
 
     return Ok(program);
 }
 
-fn walk(current: &mut usize, tokens: &Vec<Token>) -> anyhow::Result<ASTNode> {
-    let get_token = |current: usize| -> &Token {
-        return &tokens[current];
+/// Parses one top-level node. Open call expressions are kept on an explicit stack
+/// instead of recursing, so deeply nested input cannot overflow the call stack.
+fn walk(current: &mut usize, tokens: &[Token], max_depth: usize) -> anyhow::Result<ASTNode> {
+    let get_token = |current: usize| -> Result<&Token, ParseError> {
+        return tokens.get(current).ok_or(ParseError::UnexpectedEnd);
     };
     let consume_token = |current: &mut usize| -> &Token {
         let token = &tokens[*current];
@@ -34,75 +50,110 @@ fn walk(current: &mut usize, tokens: &Vec<Token>) -> anyhow::Result<ASTNode> {
         return token;
     };
 
-    let token = get_token(*current);
-    let token_type = token.token_type;
-    let token_value = &token.value;
+    // call expressions whose closing paren has not been reached yet, innermost last;
+    // quoted ones are closed by the next node instead of a paren
+    let mut open_calls: Vec<(CallExpression, bool)> = vec![];
 
-    if token_type == TokenType::NUMBER {
-        consume_token(current);
+    loop {
+        let token = get_token(*current)?;
+        let token_type = token.token_type;
+        let token_value = &token.value;
 
-        let ast_node = NumberLiteral {
-            node_type: ASTNodeType::NumberLiteral,
-            value: token_value.clone(),
-        };
-        return Ok(ASTNode::NumberLiteral(ast_node));
-    }
+        let mut ast_node = if token_type == TokenType::NUMBER {
+            consume_token(current);
 
-    if token_type == TokenType::STRING {
-        consume_token(current);
+            ASTNode::NumberLiteral(NumberLiteral {
+                node_type: ASTNodeType::NumberLiteral,
+                span: token.span,
+                value: token_value.clone(),
+            })
+        } else if token_type == TokenType::STRING {
+            consume_token(current);
 
-        let ast_node = StringLiteral {
-            node_type: ASTNodeType::StringLiteral,
-            value: token_value.clone(),
-        };
-        return Ok(ASTNode::StringLiteral(ast_node));
-    }
+            ASTNode::StringLiteral(StringLiteral {
+                node_type: ASTNodeType::StringLiteral,
+                span: token.span,
+                value: token_value.clone(),
+            })
+        } else if token_type == TokenType::NAME {
+            consume_token(current);
 
-    if token_type == TokenType::PAREN && token_value == "(" {
-        consume_token(current);
+            ASTNode::Identifier(Identifier {
+                node_type: ASTNodeType::Identifier,
+                span: token.span,
+                name: token_value.clone(),
+            })
+        } else if token_type == TokenType::QUOTE || (token_type == TokenType::PAREN && token_value == "(") {
+            if open_calls.len() >= max_depth {
+                return Err(ParseError::NestingTooDeep(max_depth).into());
+            }
 
-        let parent_exp_token = get_token(*current);
-        let parent_exp_token_value = &parent_exp_token.value;
+            consume_token(current);
+            let quoted = token_type == TokenType::QUOTE;
+            let (value, callee_span) = if quoted {
+                // `x, ,x and ,@x read as (quasiquote x), (unquote x) and (unquote-splicing x)
+                let value = match token_value.as_str() {
+                    "`" => QUASIQUOTE,
+                    "," => UNQUOTE,
+                    _ => UNQUOTE_SPLICING,
+                };
+                (value.to_string(), token.span)
+            } else if get_token(*current)?.token_type == TokenType::PAREN && get_token(*current)?.value == ")" {
+                // () is a call of the empty name, read as an empty list by `defmacro` and rejected elsewhere by the expander
+                (String::new(), token.span)
+            } else {
+                let parent_exp_token = consume_token(current);
+                (parent_exp_token.value.clone(), parent_exp_token.span)
+            };
 
-        let mut ast_node = CallExpression {
-            node_type: ASTNodeType::CallExpression,
-            value: parent_exp_token_value.clone(),
-            params: vec![],
-        };
+            open_calls.push((CallExpression {
+                node_type: ASTNodeType::CallExpression,
+                span: token.span,
+                value,
+                callee_span,
+                params: vec![],
+            }, quoted));
+            continue;
+        } else if token_type == TokenType::PAREN && token_value == ")" && open_calls.last().map_or(false, |(_, quoted)| !quoted) {
+            consume_token(current);
 
-        let mut exp_token = consume_token(current);
-        let mut exp_token_type = exp_token.token_type;
-        let mut exp_token_value = &exp_token.value;
+            let (mut call_expression, _) = open_calls.pop().unwrap();
+            call_expression.span = call_expression.span.to(token.span);
+            ASTNode::CallExpression(call_expression)
+        } else {
+            return Err(ParseError::UnknownToken(token_value.to_string()).into());
+        };
 
         loop {
-            if exp_token_type == TokenType::PAREN && exp_token_value == ")" {
-                break;
+            match open_calls.last_mut() {
+                Some((quote, true)) => {
+                    quote.span = quote.span.to(ast_node.get_span());
+                    quote.params.push(Rc::new(ast_node));
+                    ast_node = ASTNode::CallExpression(open_calls.pop().unwrap().0);
+                }
+                Some((parent, false)) => {
+                    parent.params.push(Rc::new(ast_node));
+                    break;
+                }
+                None => return Ok(ast_node),
             }
-
-            let child_node = walk(current, &tokens)?;
-            ast_node.params.push(Rc::new(child_node));
-
-            exp_token = get_token(*current);
-            exp_token_type = exp_token.token_type;
-            exp_token_value = &exp_token.value;
         }
-
-        consume_token(current);
-
-        return Ok(ASTNode::CallExpression(ast_node));
     }
-
-    return Err(ParseError::UnknownToken(token_value.to_string()).into());
 }
 
 #[derive(Debug, Error)]
 pub enum ParseError {
     #[error("Error: Unknown token: {0}")]
-    UnknownToken(String)
+    UnknownToken(String),
+    #[error("Error: Nesting too deep: more than {0} levels of call expressions")]
+    NestingTooDeep(usize),
+    #[error("Error: Unexpected end of input")]
+    UnexpectedEnd,
 }
 
 #[cfg(test)]
 mod parser_tests {
+    use crate::span::Span;
     use std::rc::Rc;
     use crate::ast::{ASTNodeType, Program, CallExpression, NumberLiteral, ASTNode, StringLiteral};
     use crate::parser::parser;
@@ -111,20 +162,20 @@ mod parser_tests {
     #[test]
     fn test_parse_valid_code() {
         let tokens = vec![
-            Token { token_type: TokenType::PAREN, value: "(".to_string() },
-            Token { token_type: TokenType::NAME, value: "add".to_string() },
-            Token { token_type: TokenType::NUMBER, value: "2".to_string() },
-            Token { token_type: TokenType::PAREN, value: "(".to_string() },
-            Token { token_type: TokenType::NAME, value: "subtract".to_string() },
-            Token { token_type: TokenType::NUMBER, value: "4".to_string() },
-            Token { token_type: TokenType::NUMBER, value: "2".to_string() },
-            Token { token_type: TokenType::PAREN, value: ")".to_string() },
-            Token { token_type: TokenType::PAREN, value: ")".to_string() },
-            Token { token_type: TokenType::PAREN, value: "(".to_string() },
-            Token { token_type: TokenType::NAME, value: "fullName".to_string() },
-            Token { token_type: TokenType::STRING, value: "hoge".to_string() },
-            Token { token_type: TokenType::STRING, value: "foo".to_string() },
-            Token { token_type: TokenType::PAREN, value: ")".to_string() },
+            Token { token_type: TokenType::PAREN, value: "(".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NAME, value: "add".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NUMBER, value: "2".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: "(".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NAME, value: "subtract".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NUMBER, value: "4".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NUMBER, value: "2".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: ")".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: ")".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: "(".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NAME, value: "fullName".to_string(), span: Span::default() },
+            Token { token_type: TokenType::STRING, value: "hoge".to_string(), span: Span::default() },
+            Token { token_type: TokenType::STRING, value: "foo".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: ")".to_string(), span: Span::default() },
         ];
 
         let parse_result = parser(tokens);
@@ -132,25 +183,33 @@ mod parser_tests {
 
         let expected_program = Program {
             node_type: ASTNodeType::Program,
+            span: Span::default(),
             body: vec![
                 Rc::new(ASTNode::CallExpression(CallExpression {
                     node_type: ASTNodeType::CallExpression,
+                    span: Span::default(),
                     value: "add".to_string(),
+                    callee_span: Span::default(),
                     params: vec![
                         Rc::new(ASTNode::NumberLiteral(NumberLiteral {
                             node_type: ASTNodeType::NumberLiteral,
+                            span: Span::default(),
                             value: "2".to_string(),
                         })),
                         Rc::new(ASTNode::CallExpression(CallExpression {
                             node_type: ASTNodeType::CallExpression,
+                            span: Span::default(),
                             value: "subtract".to_string(),
+                            callee_span: Span::default(),
                             params: vec![
                                 Rc::new(ASTNode::NumberLiteral(NumberLiteral {
                                     node_type: ASTNodeType::NumberLiteral,
+                                    span: Span::default(),
                                     value: "4".to_string(),
                                 })),
                                 Rc::new(ASTNode::NumberLiteral(NumberLiteral {
                                     node_type: ASTNodeType::NumberLiteral,
+                                    span: Span::default(),
                                     value: "2".to_string(),
                                 })),
                             ],
@@ -159,14 +218,18 @@ mod parser_tests {
                 })),
                 Rc::new(ASTNode::CallExpression(CallExpression {
                     node_type: ASTNodeType::CallExpression,
+                    span: Span::default(),
                     value: "fullName".to_string(),
+                    callee_span: Span::default(),
                     params: vec![
                         Rc::new(ASTNode::StringLiteral(StringLiteral {
                             node_type: ASTNodeType::StringLiteral,
+                            span: Span::default(),
                             value: "hoge".to_string(),
                         })),
                         Rc::new(ASTNode::StringLiteral(StringLiteral {
                             node_type: ASTNodeType::StringLiteral,
+                            span: Span::default(),
                             value: "foo".to_string(),
                         })),
                     ]
@@ -176,4 +239,91 @@ mod parser_tests {
 
         assert_eq!(result_program, expected_program);
     }
+
+    fn nested_call_tokens(depth: usize) -> Vec<Token> {
+        let mut tokens = vec![];
+        for _ in 0..depth {
+            tokens.push(Token { token_type: TokenType::PAREN, value: "(".to_string(), span: Span::default() });
+            tokens.push(Token { token_type: TokenType::NAME, value: "add".to_string(), span: Span::default() });
+        }
+        tokens.push(Token { token_type: TokenType::NUMBER, value: "1".to_string(), span: Span::default() });
+        for _ in 0..depth {
+            tokens.push(Token { token_type: TokenType::PAREN, value: ")".to_string(), span: Span::default() });
+        }
+        tokens
+    }
+
+    fn depth_of(node: &ASTNode) -> usize {
+        let mut depth = 0;
+        let mut node = node;
+        while let ASTNode::CallExpression(call_expression) = node {
+            depth += 1;
+            node = call_expression.params[0].as_ref();
+        }
+        depth
+    }
+
+    #[test]
+    fn test_parse_nesting_up_to_max_depth() {
+        use crate::parser::parser_with_max_depth;
+
+        let parse_result = parser_with_max_depth(nested_call_tokens(100), 100);
+        let result_program = parse_result.unwrap();
+
+        assert_eq!(result_program.body.len(), 1);
+        assert_eq!(depth_of(&result_program.body[0]), 100);
+    }
+
+    #[test]
+    fn test_parse_nesting_too_deep() {
+        let parse_result = parser(nested_call_tokens(1_000_000));
+        let result_error = parse_result.unwrap_err();
+        let route_cause = result_error.root_cause();
+
+        assert_eq!(format!("{}", route_cause), "Error: Nesting too deep: more than 1000 levels of call expressions");
+    }
+
+    #[test]
+    fn test_parse_quotes_and_identifiers() {
+        use crate::tokenizer::tokenizer;
+
+        let result_program = parser(tokenizer("`(print ,x ,@more) ()".to_string()).unwrap()).unwrap();
+
+        let ASTNode::CallExpression(quasiquote) = result_program.body[0].as_ref() else { panic!("not a call") };
+        let ASTNode::CallExpression(print) = quasiquote.params[0].as_ref() else { panic!("not a call") };
+        let ASTNode::CallExpression(unquote) = print.params[0].as_ref() else { panic!("not a call") };
+        let ASTNode::CallExpression(unquote_splicing) = print.params[1].as_ref() else { panic!("not a call") };
+        assert_eq!((quasiquote.value.as_str(), quasiquote.span.start.offset, quasiquote.span.end.offset), ("quasiquote", 0, 18));
+        assert_eq!((unquote.value.as_str(), unquote.span.start.offset, unquote.span.end.offset), ("unquote", 8, 10));
+        assert!(matches!(unquote.params[0].as_ref(), ASTNode::Identifier(identifier) if identifier.name == "x"));
+        assert_eq!(unquote_splicing.value, "unquote-splicing");
+        assert!(matches!(result_program.body[1].as_ref(), ASTNode::CallExpression(empty) if empty.value.is_empty() && empty.params.is_empty()));
+    }
+
+    #[test]
+    fn test_parse_quote_without_form() {
+        use crate::tokenizer::tokenizer;
+
+        let result_error = parser(tokenizer("(print `)".to_string()).unwrap()).unwrap_err();
+
+        assert_eq!(result_error.root_cause().to_string(), "Error: Unknown token: )");
+    }
+
+    #[test]
+    fn test_parse_unclosed_paren() {
+        use crate::tokenizer::tokenizer;
+
+        let result_error = parser(tokenizer("(add 1".to_string()).unwrap()).unwrap_err();
+
+        assert_eq!(result_error.root_cause().to_string(), "Error: Unexpected end of input");
+    }
+
+    #[test]
+    fn test_parse_lone_paren() {
+        use crate::tokenizer::tokenizer;
+
+        let result_error = parser(tokenizer("(".to_string()).unwrap()).unwrap_err();
+
+        assert_eq!(result_error.root_cause().to_string(), "Error: Unexpected end of input");
+    }
 }
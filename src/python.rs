@@ -0,0 +1,240 @@
+use crate::ast::NewASTNode;
+use crate::eval::IF;
+
+/// Defines the builtins with the evaluator's semantics: `divide` truncates toward zero,
+/// `eq` never equates values of different types, and `print` writes `true`, `false` and `nil`.
+/// Arity, type and overflow errors end the program with the evaluator's message, less its position.
+const PRELUDE: &str = "\
+import builtins as _builtins
+import sys as _sys
+
+_MIN, _MAX = -2 ** 63, 2 ** 63 - 1
+
+
+def _fail(message):
+    _sys.stderr.write('Error: ' + message + '\\n')
+    _sys.exit(1)
+
+
+def _type_name(value):
+    if value is None:
+        return 'nil'
+    if isinstance(value, bool):
+        return 'bool'
+    return 'number' if isinstance(value, int) else 'string'
+
+
+def _expect(name, args, count):
+    if len(args) != count:
+        _fail('%s expects %d arguments but got %d' % (name, count, len(args)))
+    return args
+
+
+def _numbers(name, args):
+    for arg in _expect(name, args, 2):
+        if _type_name(arg) != 'number':
+            _fail('%s expects a number but got a %s' % (name, _type_name(arg)))
+    return args
+
+
+def _checked(name, number):
+    if not _MIN <= number <= _MAX:
+        _fail('Integer overflow in ' + name)
+    return number
+
+
+def _truthy(value):
+    if _type_name(value) != 'bool':
+        _fail('if expects a bool but got a ' + _type_name(value))
+    return value
+
+
+def add(*args):
+    a, b = _numbers('add', args)
+    return _checked('add', a + b)
+
+
+def subtract(*args):
+    a, b = _numbers('subtract', args)
+    return _checked('subtract', a - b)
+
+
+def multiply(*args):
+    a, b = _numbers('multiply', args)
+    return _checked('multiply', a * b)
+
+
+def divide(*args):
+    a, b = _numbers('divide', args)
+    if b == 0:
+        _fail('Division by zero')
+    quotient = abs(a) // abs(b)
+    return _checked('divide', quotient if (a >= 0) == (b >= 0) else -quotient)
+
+
+def concat(*args):
+    for arg in args:
+        if _type_name(arg) != 'string':
+            _fail('concat expects a string but got a ' + _type_name(arg))
+    return ''.join(args)
+
+
+def eq(*args):
+    a, b = _expect('eq', args, 2)
+    return type(a) is type(b) and a == b
+
+
+def lt(*args):
+    a, b = _numbers('lt', args)
+    return a < b
+
+
+def le(*args):
+    a, b = _numbers('le', args)
+    return a <= b
+
+
+def gt(*args):
+    a, b = _numbers('gt', args)
+    return a > b
+
+
+def ge(*args):
+    a, b = _numbers('ge', args)
+    return a >= b
+
+
+def _show(value):
+    if value is None:
+        return 'nil'
+    if isinstance(value, bool):
+        return 'true' if value else 'false'
+    return str(value)
+
+
+def print(*args):
+    _builtins.print(*map(_show, args))
+";
+
+/// Names that cannot be used as Python identifiers.
+const KEYWORDS: [&str; 35] = [
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in",
+    "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+/// Generates Python 3 source: the prelude followed by one line per statement.
+/// Calls to functions outside the prelude are emitted as they are, like `generate_code` does.
+pub fn generate_python(new_node: &NewASTNode) -> String {
+    let body = generate(new_node);
+
+    format!("{}\n\n{}", PRELUDE, body)
+}
+
+enum Step<'a> {
+    Enter(&'a NewASTNode),
+    Exit(&'a NewASTNode),
+}
+
+/// Generates code without recursion, like `code_generator`.
+fn generate(new_node: &NewASTNode) -> String {
+    let mut steps: Vec<Step> = vec![Step::Enter(new_node)];
+    let mut codes: Vec<String> = vec![];
+
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Enter(node) => match node {
+                NewASTNode::NewProgram(new_program) => {
+                    steps.push(Step::Exit(node));
+                    steps.extend(new_program.body.iter().rev().map(Step::Enter));
+                }
+                NewASTNode::ExpressionStatement(expression_statement) => {
+                    steps.push(Step::Enter(&expression_statement.expression));
+                }
+                NewASTNode::CallExpressionWithCallee(call_expression_with_callee) => {
+                    steps.push(Step::Exit(node));
+                    steps.extend(call_expression_with_callee.arguments.iter().rev().map(Step::Enter));
+                }
+                NewASTNode::Identifier(identifier) => codes.push(identifier_code(&identifier.name)),
+                NewASTNode::NumberLiteral(number_literal) => codes.push(number_literal.value.clone()),
+                NewASTNode::StringLiteral(string_literal) => codes.push(string_code(&string_literal.value)),
+            },
+            Step::Exit(node) => match node {
+                NewASTNode::NewProgram(new_program) => {
+                    let body = codes.split_off(codes.len() - new_program.body.len());
+
+                    codes.push(body.join("\n"));
+                }
+                NewASTNode::CallExpressionWithCallee(call_expression_with_callee) => {
+                    let callee = &call_expression_with_callee.callee;
+                    let arguments = codes.split_off(codes.len() - call_expression_with_callee.arguments.len());
+
+                    match arguments.as_slice() {
+                        [condition, then_branch, else_branch] if callee.name == IF => {
+                            codes.push(format!("({} if _truthy({}) else {})", then_branch, condition, else_branch));
+                        }
+                        _ => codes.push(format!("{}({})", identifier_code(&callee.name), arguments.join(", "))),
+                    }
+                }
+                _ => unreachable!("only nodes with children are exited"),
+            },
+        }
+    }
+
+    codes.pop().unwrap_or_default()
+}
+
+/// Names may contain `-`, which Python does not allow; keywords get a trailing `_`.
+fn identifier_code(name: &str) -> String {
+    let name = name.replace('-', "_");
+    if KEYWORDS.contains(&name.as_str()) {
+        return format!("{}_", name);
+    }
+
+    name
+}
+
+/// A single-quoted Python string literal.
+fn string_code(value: &str) -> String {
+    let mut code = String::from("'");
+    for ch in value.chars() {
+        match ch {
+            '\'' => code.push_str("\\'"),
+            '\\' => code.push_str("\\\\"),
+            '\n' => code.push_str("\\n"),
+            '\r' => code.push_str("\\r"),
+            '\t' => code.push_str("\\t"),
+            ch if ch.is_control() => code.push_str(&format!("\\x{:02x}", ch as u32)),
+            ch => code.push(ch),
+        }
+    }
+    code.push('\'');
+
+    code
+}
+
+#[cfg(test)]
+mod python_tests {
+    use crate::python::{generate_python, identifier_code, string_code};
+    use crate::test_support::new_ast::{call, number, program, statement, string};
+
+    #[test]
+    fn test_generate_python() {
+        let new_program = program(vec![
+            statement(call("add", vec![number("2"), call("subtract", vec![number("4"), number("2")])])),
+            statement(call("if", vec![call("lt", vec![number("1"), number("2")]), string("yes"), call("full-name", vec![])])),
+        ]);
+
+        let code = generate_python(&new_program);
+
+        assert!(code.starts_with("import builtins as _builtins\n"));
+        assert!(code.ends_with("\n\nadd(2, subtract(4, 2))\n('yes' if _truthy(lt(1, 2)) else full_name())"));
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(string_code("it's a \\ \"test\"\n\u{7}é"), "'it\\'s a \\\\ \"test\"\\n\\x07é'");
+        assert_eq!(identifier_code("class"), "class_");
+        assert_eq!(identifier_code("print"), "print");
+    }
+}
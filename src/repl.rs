@@ -0,0 +1,357 @@
+use std::fs::OpenOptions;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+use crate::ast::Program;
+use crate::compiler::compile_program;
+use crate::eval::{eval, Environment, Value};
+use crate::expander::Expander;
+use crate::module::Loader;
+use crate::optimizer::OptLevel;
+use crate::parser::parser;
+use crate::tokenizer::tokenizer;
+
+/// History file used by `repl` when `--history` is not given.
+pub const DEFAULT_HISTORY_FILE: &str = ".super-tiny-history";
+
+/// Where forms are read from, for errors and imports: a file in the current directory that need not exist.
+const REPL_PATH: &str = "<repl>";
+
+const HELP: &str = "\
+:mode eval|js|ast  print the value, the generated JavaScript or the AST of each form
+:tokens <code>     print the tokens of <code>
+:ast <code>        print the AST of <code>
+:history           print the forms entered so far
+:help              print this help
+:quit              leave the REPL";
+
+/// What the REPL prints for each complete form.
+#[derive(Debug, PartialEq, Clone, Copy, EnumString, Display)]
+pub enum ReplMode {
+    #[strum(serialize = "eval")]
+    Eval,
+    #[strum(serialize = "js")]
+    Js,
+    #[strum(serialize = "ast")]
+    Ast,
+}
+
+/// Forms entered in this and earlier sessions, appended to a file as they are read.
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+}
+
+impl History {
+    /// Loads the entries already in `path`; a missing file starts an empty history.
+    pub fn load(path: Option<PathBuf>) -> History {
+        let entries = path.as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|content| content.lines().map(|line| line.to_string()).collect())
+            .unwrap_or_default();
+
+        History { path, entries }
+    }
+
+    /// Multi-line forms are stored on one line.
+    fn add(&mut self, form: &str) -> std::io::Result<()> {
+        let entry = form.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", entry)?;
+        }
+        self.entries.push(entry);
+
+        Ok(())
+    }
+}
+
+pub struct Repl {
+    mode: ReplMode,
+    environment: Environment,
+    /// Keeps the macros defined or imported so far.
+    expander: Expander,
+    /// Keeps the modules imported so far, relative to the current directory.
+    loader: Loader,
+    history: History,
+    /// Lines of a form whose parens are not closed yet.
+    pending: String,
+}
+
+/// Result of feeding one line to the REPL.
+#[derive(Debug, PartialEq)]
+pub enum ReplStep {
+    /// The form is complete; holds what to print, possibly nothing.
+    Output(String),
+    /// The form continues on the next line.
+    Continue,
+    Quit,
+}
+
+impl Repl {
+    pub fn new(history: History) -> Repl {
+        Repl {
+            mode: ReplMode::Eval,
+            environment: Environment::new(),
+            expander: Expander::new(),
+            loader: Loader::new(Path::new(".")),
+            history,
+            pending: String::new(),
+        }
+    }
+
+    pub fn is_continuing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn feed(&mut self, line: &str) -> ReplStep {
+        if !self.is_continuing() && line.trim_start().starts_with(':') {
+            return self.meta_command(line.trim());
+        }
+
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        if self.pending.trim().is_empty() {
+            self.pending.clear();
+            return ReplStep::Output(String::new());
+        }
+        if paren_depth(&self.pending).map_or(true, |depth| depth > 0) {
+            return ReplStep::Continue;
+        }
+
+        let form = std::mem::take(&mut self.pending);
+        let mut output = match self.history.add(&form) {
+            Ok(()) => String::new(),
+            Err(error) => format!("Warning: Cannot write history: {}\n", error),
+        };
+        match self.run_form(form) {
+            Ok(result) => output.push_str(&result),
+            Err(error) => output.push_str(&error.to_string()),
+        }
+
+        ReplStep::Output(output.trim_end().to_string())
+    }
+
+    fn meta_command(&mut self, line: &str) -> ReplStep {
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        let output = match command {
+            ":quit" => return ReplStep::Quit,
+            ":help" => HELP.to_string(),
+            ":history" => self.history.entries.join("\n"),
+            ":mode" => match ReplMode::from_str(argument) {
+                Ok(mode) => {
+                    self.mode = mode;
+                    format!("mode: {}", mode)
+                }
+                Err(_) => format!("Error: Unknown mode: {} (expected eval, js or ast)", argument),
+            },
+            ":tokens" => match tokenizer(argument.to_string()) {
+                Ok(tokens) => tokens.iter()
+                    .map(|token| format!("{} {}", token.token_type, token.value))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                Err(error) => error.to_string(),
+            },
+            ":ast" => match parse(argument.to_string()) {
+                Ok(program) => format_ast(&program),
+                Err(error) => error.to_string(),
+            },
+            _ => format!("Error: Unknown command: {} (try :help)", command),
+        };
+
+        ReplStep::Output(output)
+    }
+
+    fn run_form(&mut self, form: String) -> anyhow::Result<String> {
+        match self.mode {
+            ReplMode::Js => {
+                let (program, mut output) = self.expand(form)?;
+                output.push_str(&compile_program(program, OptLevel::O0)?);
+
+                Ok(output)
+            }
+            ReplMode::Ast => Ok(format_ast(&parse(form)?)),
+            ReplMode::Eval => {
+                let (program, mut output) = self.expand(form)?;
+                let mut out: Vec<u8> = vec![];
+                let values = eval(&program, &self.environment, &mut out)?;
+
+                output.push_str(&String::from_utf8(out)?);
+                for value in values.iter().filter(|value| **value != Value::Nil) {
+                    output.push_str(&format!("{}\n", value));
+                }
+
+                Ok(output)
+            }
+        }
+    }
+
+    /// Expands `form` with the macros of the forms before it, after the code of the modules it imports first.
+    /// Also returns its warnings, one per line.
+    fn expand(&mut self, form: String) -> anyhow::Result<(Program, String)> {
+        let program = self.loader.load(&mut self.expander, Path::new(REPL_PATH), parse(form)?)?;
+        let warnings = self.expander.take_warnings().iter().map(|warning| format!("{}\n", warning)).collect::<String>();
+
+        Ok((program, warnings))
+    }
+}
+
+fn parse(code: String) -> anyhow::Result<Program> {
+    parser(tokenizer(code)?)
+}
+
+#[cfg(feature = "serde")]
+fn format_ast(program: &Program) -> String {
+    use crate::ast::ASTNode;
+    use crate::json::to_json;
+
+    to_json(&ASTNode::Program(program.clone())).unwrap_or_else(|error| error.to_string())
+}
+
+#[cfg(not(feature = "serde"))]
+fn format_ast(program: &Program) -> String {
+    format!("{:#?}", program)
+}
+
+/// Open parens minus close parens, ignoring those inside string literals,
+/// or `None` while a string literal is still open.
+fn paren_depth(code: &str) -> Option<isize> {
+    let mut depth = 0;
+    let mut in_string = false;
+
+    for ch in code.chars() {
+        match (in_string, ch) {
+            // Like the tokenizer, either quote ends a string.
+            (_, '"' | '\'') => in_string = !in_string,
+            (true, _) => {}
+            (false, '(') => depth += 1,
+            (false, ')') => depth -= 1,
+            (false, _) => {}
+        }
+    }
+
+    if in_string { None } else { Some(depth) }
+}
+
+/// Reads forms from `input` until EOF or `:quit`, printing a prompt before each line.
+pub fn start(input: impl BufRead, output: &mut impl Write, history: History) -> std::io::Result<()> {
+    let mut repl = Repl::new(history);
+    let mut lines = input.lines();
+
+    loop {
+        write!(output, "{}", if repl.is_continuing() { ". " } else { "> " })?;
+        output.flush()?;
+
+        let Some(line) = lines.next() else {
+            writeln!(output)?;
+            return Ok(());
+        };
+        match repl.feed(&line?) {
+            ReplStep::Output(text) if text.is_empty() => {}
+            ReplStep::Output(text) => writeln!(output, "{}", text)?,
+            ReplStep::Continue => {}
+            ReplStep::Quit => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use std::path::PathBuf;
+    use crate::repl::{paren_depth, History, Repl, ReplStep};
+
+    fn output(text: &str) -> ReplStep {
+        ReplStep::Output(text.to_string())
+    }
+
+    #[test]
+    fn test_paren_depth() {
+        assert_eq!(paren_depth("(add 1 (subtract 2"), Some(2));
+        assert_eq!(paren_depth("(concat ')' \"((\")"), Some(0));
+        assert_eq!(paren_depth("1)"), Some(-1));
+        assert_eq!(paren_depth("(print 'hoge)"), None);
+    }
+
+    #[test]
+    fn test_feed_multi_line_form() {
+        let mut repl = Repl::new(History::load(None));
+
+        assert_eq!(repl.feed("(add 2"), ReplStep::Continue);
+        assert_eq!(repl.feed("  (subtract 4 2))"), output("4"));
+        assert_eq!(repl.feed("(print 'hoge') (eq 1 1)"), output("hoge\ntrue"));
+        assert_eq!(repl.feed(""), output(""));
+        assert_eq!(repl.feed("(divide 1 0)"), output("Error: Division by zero at 1:1"));
+    }
+
+    #[test]
+    fn test_feed_continues_open_string() {
+        let mut repl = Repl::new(History::load(None));
+
+        assert_eq!(repl.feed("'hoge"), ReplStep::Continue);
+        assert_eq!(repl.feed("foo'"), output("hoge\nfoo"));
+    }
+
+    #[test]
+    fn test_macros_stay_defined() {
+        let mut repl = Repl::new(History::load(None));
+
+        assert_eq!(repl.feed("(defmacro double (x) `(add ,x ,x))"), output(""));
+        assert_eq!(repl.feed("(double 21)"), output("42"));
+    }
+
+    #[test]
+    fn test_import_modules() {
+        let dir = std::env::temp_dir().join(format!("super-tiny-repl-modules-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("math.lisp");
+        std::fs::write(&lib, "(defmacro double (x) `(add ,x ,x)) (export double) (print 'math loaded')").unwrap();
+        let import = format!("(import \"{}\")", lib.display());
+        let mut repl = Repl::new(History::load(None));
+
+        assert_eq!(repl.feed(&import), output("math loaded"));
+        assert_eq!(repl.feed("(double 21)"), output("42"));
+        // Imported once, so its code does not run again and its macros are the same.
+        assert_eq!(repl.feed(&import), output(""));
+        assert!(matches!(repl.feed("(import \"missing.lisp\")"), ReplStep::Output(error) if error.starts_with("Error: Cannot import missing.lisp: ")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_macro_warnings() {
+        let mut repl = Repl::new(History::load(None));
+
+        assert_eq!(repl.feed("(defmacro print (x) x) (print 1)"), output("Warning: Macro print shadows the builtin function print at 1:11\n1"));
+        assert_eq!(repl.feed("(print 2)"), output("2"));
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let mut repl = Repl::new(History::load(None));
+
+        assert_eq!(repl.feed(":tokens (add 1)"), output("paren (\nname add\nnumber 1\nparen )"));
+        assert_eq!(repl.feed(":mode wasm"), output("Error: Unknown mode: wasm (expected eval, js or ast)"));
+        assert_eq!(repl.feed(":mode js"), output("mode: js"));
+        assert_eq!(repl.feed(":nope"), output("Error: Unknown command: :nope (try :help)"));
+        assert_eq!(repl.feed(":quit"), ReplStep::Quit);
+    }
+
+    #[test]
+    fn test_history_is_persisted() {
+        let path: PathBuf = std::env::temp_dir().join(format!("super-tiny-history-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut repl = Repl::new(History::load(Some(path.clone())));
+        repl.feed("(add 1");
+        repl.feed("  2)");
+        repl.feed(":history");
+
+        let mut repl = Repl::new(History::load(Some(path.clone())));
+        repl.feed("(print 1)");
+
+        assert_eq!(repl.feed(":history"), output("(add 1 2)\n(print 1)"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
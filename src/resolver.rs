@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use thiserror::Error;
+use crate::ast::{ASTNode, CallExpression, Identifier, Program};
+use crate::expander::{parse_defmacro, DEFMACRO, QUASIQUOTE, UNQUOTE, UNQUOTE_SPLICING};
+use crate::functions::FunctionRegistry;
+use crate::module::{EXPORT, IMPORT};
+use crate::span::{Position, Span};
+
+#[derive(Eq, Hash, Debug, PartialEq, Clone, Copy)]
+pub struct ScopeId(usize);
+
+#[derive(Eq, Hash, Debug, PartialEq, Clone, Copy)]
+pub struct DeclarationId(usize);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DeclarationKind {
+    Builtin,
+    Macro,
+    Parameter,
+}
+
+/// A name bound in a scope. Builtins have an empty span.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Declaration {
+    pub name: String,
+    pub kind: DeclarationKind,
+    pub span: Span,
+    pub scope: ScopeId,
+}
+
+/// The builtins, the macros of the program, or the parameters of one macro.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Scope {
+    pub parent: Option<ScopeId>,
+    names: HashMap<String, DeclarationId>,
+}
+
+/// A use of a name: the callee of a call to a builtin or macro, or an unquoted macro parameter.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    pub declaration: DeclarationId,
+}
+
+/// What `resolve` found in a program, in source order.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Resolution {
+    pub scopes: Vec<Scope>,
+    pub declarations: Vec<Declaration>,
+    pub references: Vec<Reference>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolution {
+    pub fn declaration(&self, id: DeclarationId) -> &Declaration {
+        &self.declarations[id.0]
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|diagnostic| diagnostic.is_error())
+    }
+}
+
+/// Links names in `program` to the builtins and the macros it defines; see `Resolver`.
+pub fn resolve(program: &Program) -> Resolution {
+    Resolver::new().resolve(program)
+}
+
+/// Builds the scopes of a program before macro expansion.
+/// Macros are declared in order, so a call before the definition is an ordinary call, as for the expander.
+/// Arguments of macro calls are not resolved: they are only code once the macro places them.
+/// Malformed forms are skipped and left for the expander to report.
+pub struct Resolver {
+    resolution: Resolution,
+    /// The scope of the macros of the program, inside the scope of the builtins.
+    globals: ScopeId,
+}
+
+impl Default for Resolver {
+    fn default() -> Resolver {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        let mut resolver = Resolver {
+            resolution: Resolution { scopes: vec![], declarations: vec![], references: vec![], diagnostics: vec![] },
+            globals: ScopeId(0),
+        };
+        let builtins = resolver.scope(None);
+        let mut names = FunctionRegistry::with_builtins().names().map(str::to_string).collect::<Vec<String>>();
+        names.sort();
+        for name in names {
+            resolver.declare(builtins, name, DeclarationKind::Builtin, Span::default());
+        }
+        resolver.globals = resolver.scope(Some(builtins));
+
+        resolver
+    }
+
+    /// Declares a macro defined before the program, such as an imported one or one from an earlier REPL form.
+    /// It was diagnosed where it was defined.
+    pub fn declare_macro(&mut self, name: &str, span: Span) {
+        self.insert(self.globals, name.to_string(), DeclarationKind::Macro, span);
+    }
+
+    pub fn resolve(mut self, program: &Program) -> Resolution {
+        for node in &program.body {
+            match node.as_ref() {
+                ASTNode::CallExpression(call_expression) if call_expression.value == DEFMACRO => self.define(call_expression),
+                _ => self.expression(node),
+            }
+        }
+
+        self.resolution
+    }
+
+    fn define(&mut self, defmacro: &CallExpression) {
+        let Ok(definition) = parse_defmacro(defmacro) else {
+            return;
+        };
+
+        let scope = self.scope(Some(self.globals));
+        for (name, span) in definition.params.into_iter().chain(definition.rest) {
+            self.declare(scope, name, DeclarationKind::Parameter, span);
+        }
+        match definition.body.as_ref() {
+            ASTNode::Identifier(identifier) => self.parameter(scope, identifier),
+            ASTNode::CallExpression(quasiquote) => self.template(scope, &quasiquote.params[0]),
+            _ => {}
+        }
+
+        // Declared after the body, as the expander defines it: a macro cannot call itself while being defined.
+        self.declare(self.globals, definition.name.name.clone(), DeclarationKind::Macro, definition.name.span);
+    }
+
+    /// Code outside macro bodies, where nothing binds a symbol.
+    fn expression(&mut self, node: &Rc<ASTNode>) {
+        let call_expression = match node.as_ref() {
+            ASTNode::CallExpression(call_expression) => call_expression,
+            ASTNode::Identifier(identifier) => return self.diagnose(Diagnostic::UnboundSymbol(identifier.name.clone(), identifier.span.start)),
+            _ => return,
+        };
+        if [DEFMACRO, IMPORT, EXPORT, QUASIQUOTE, UNQUOTE, UNQUOTE_SPLICING].contains(&call_expression.value.as_str()) {
+            return;
+        }
+
+        let is_macro_call = self.callee(call_expression) == Some(DeclarationKind::Macro);
+        if !is_macro_call {
+            for param in &call_expression.params {
+                self.expression(param);
+            }
+        }
+    }
+
+    /// The quasiquoted body of a macro. Its symbols are renamed when it is instantiated, so only
+    /// unquoted parameters and callees are uses of names.
+    fn template(&mut self, scope: ScopeId, node: &Rc<ASTNode>) {
+        let ASTNode::CallExpression(call_expression) = node.as_ref() else {
+            return;
+        };
+        match (call_expression.value.as_str(), call_expression.params.as_slice()) {
+            (UNQUOTE | UNQUOTE_SPLICING, [param]) => {
+                if let ASTNode::Identifier(identifier) = param.as_ref() {
+                    self.parameter(scope, identifier);
+                }
+            }
+            (UNQUOTE | UNQUOTE_SPLICING | QUASIQUOTE, _) => {}
+            _ => {
+                self.callee(call_expression);
+                for param in &call_expression.params {
+                    self.template(scope, param);
+                }
+            }
+        }
+    }
+
+    /// Links an unquoted symbol to a parameter of the macro whose `scope` it is in.
+    fn parameter(&mut self, scope: ScopeId, identifier: &Identifier) {
+        match self.resolution.scopes[scope.0].names.get(&identifier.name) {
+            Some(&declaration) => self.refer(identifier.name.clone(), identifier.span, declaration),
+            None => self.diagnose(Diagnostic::UnboundSymbol(identifier.name.clone(), identifier.span.start)),
+        }
+    }
+
+    /// Links the callee of `call_expression` to a builtin or macro, if it names one; returns its kind.
+    fn callee(&mut self, call_expression: &CallExpression) -> Option<DeclarationKind> {
+        let declaration = self.lookup(self.globals, &call_expression.value)?;
+        self.refer(call_expression.value.clone(), call_expression.callee_span, declaration);
+
+        Some(self.resolution.declaration(declaration).kind)
+    }
+
+    fn lookup(&self, scope: ScopeId, name: &str) -> Option<DeclarationId> {
+        let mut scope = Some(scope);
+        while let Some(id) = scope {
+            let scope_data = &self.resolution.scopes[id.0];
+            if let Some(&declaration) = scope_data.names.get(name) {
+                return Some(declaration);
+            }
+            scope = scope_data.parent;
+        }
+
+        None
+    }
+
+    fn scope(&mut self, parent: Option<ScopeId>) -> ScopeId {
+        self.resolution.scopes.push(Scope { parent, names: HashMap::new() });
+        ScopeId(self.resolution.scopes.len() - 1)
+    }
+
+    fn declare(&mut self, scope: ScopeId, name: String, kind: DeclarationKind, span: Span) {
+        if let Some(&previous) = self.resolution.scopes[scope.0].names.get(&name) {
+            let previous = self.resolution.declaration(previous).span;
+            return self.diagnose(Diagnostic::DuplicateDefinition(name, previous.start, span.start));
+        }
+        if kind == DeclarationKind::Macro {
+            let shadowed = self.lookup(scope, &name).map(|declaration| self.resolution.declaration(declaration).kind);
+            if shadowed == Some(DeclarationKind::Builtin) {
+                self.diagnose(Diagnostic::ShadowsBuiltin(name.clone(), span.start));
+            }
+        }
+
+        self.insert(scope, name, kind, span);
+    }
+
+    fn insert(&mut self, scope: ScopeId, name: String, kind: DeclarationKind, span: Span) {
+        let id = DeclarationId(self.resolution.declarations.len());
+        self.resolution.declarations.push(Declaration { name: name.clone(), kind, span, scope });
+        self.resolution.scopes[scope.0].names.insert(name, id);
+    }
+
+    fn refer(&mut self, name: String, span: Span, declaration: DeclarationId) {
+        self.resolution.references.push(Reference { name, span, declaration });
+    }
+
+    fn diagnose(&mut self, diagnostic: Diagnostic) {
+        self.resolution.diagnostics.push(diagnostic);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Error)]
+pub enum Diagnostic {
+    #[error("Error: Unbound symbol: {0} at {1}")]
+    UnboundSymbol(String, Position),
+    #[error("Error: {0} is already defined at {1}, again at {2}")]
+    DuplicateDefinition(String, Position, Position),
+    #[error("Warning: Macro {0} shadows the builtin function {0} at {1}")]
+    ShadowsBuiltin(String, Position),
+}
+
+impl Diagnostic {
+    /// Whether the program cannot be compiled; other diagnostics are warnings.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Diagnostic::ShadowsBuiltin(..))
+    }
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use crate::parser::parser;
+    use crate::resolver::{resolve, DeclarationKind, Diagnostic, Resolution};
+    use crate::span::Position;
+    use crate::tokenizer::tokenizer;
+
+    fn resolve_code(code: &str) -> Resolution {
+        resolve(&parser(tokenizer(code.to_string()).unwrap()).unwrap())
+    }
+
+    fn position(offset: usize) -> Position {
+        Position { offset, line: 1, column: offset }
+    }
+
+    #[test]
+    fn test_resolve_links_uses_to_declarations() {
+        let resolution = resolve_code("(defmacro twice (x) `(add ,x ,x)) (print (twice 1))");
+
+        let references = resolution.references.iter()
+            .map(|reference| {
+                let declaration = resolution.declaration(reference.declaration);
+                (reference.name.as_str(), reference.span.start.offset, declaration.kind, declaration.span.start.offset)
+            })
+            .collect::<Vec<(&str, usize, DeclarationKind, usize)>>();
+
+        assert_eq!(references, vec![
+            ("add", 22, DeclarationKind::Builtin, 0),
+            ("x", 27, DeclarationKind::Parameter, 17),
+            ("x", 30, DeclarationKind::Parameter, 17),
+            ("print", 35, DeclarationKind::Builtin, 0),
+            ("twice", 42, DeclarationKind::Macro, 10),
+        ]);
+        assert!(resolution.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_scopes() {
+        let resolution = resolve_code("(defmacro a (x) x) (defmacro b (x) x)");
+
+        let parameters = resolution.declarations.iter()
+            .filter(|declaration| declaration.kind == DeclarationKind::Parameter)
+            .collect::<Vec<_>>();
+        let parents = parameters.iter()
+            .map(|declaration| resolution.scopes[declaration.scope.0].parent)
+            .collect::<Vec<_>>();
+
+        // Each macro has its own scope for parameters, inside the scope of the macros.
+        assert_ne!(parameters[0].scope, parameters[1].scope);
+        assert_eq!(parents[0], parents[1]);
+        assert!(resolution.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_diagnostics() {
+        let diagnostics = |code: &str| resolve_code(code).diagnostics;
+
+        assert_eq!(diagnostics("(defmacro m (x) `(print ,y))"), vec![Diagnostic::UnboundSymbol("y".to_string(), position(25))]);
+        assert_eq!(diagnostics("(defmacro m (x y x) x)"), vec![Diagnostic::DuplicateDefinition("x".to_string(), position(13), position(17))]);
+        assert_eq!(diagnostics("(defmacro m (x) x) (defmacro m (y) y)"), vec![Diagnostic::DuplicateDefinition("m".to_string(), position(10), position(29))]);
+        assert_eq!(diagnostics("(defmacro add (x) x)"), vec![Diagnostic::ShadowsBuiltin("add".to_string(), position(10))]);
+        assert_eq!(diagnostics("(print (concat 'a' tmp))"), vec![Diagnostic::UnboundSymbol("tmp".to_string(), position(19))]);
+        assert!(!Diagnostic::ShadowsBuiltin("add".to_string(), position(10)).is_error());
+    }
+
+    #[test]
+    fn test_resolve_skips_macro_arguments_and_introduced_symbols() {
+        let resolution = resolve_code("(defmacro ignore (x) `(print tmp)) (ignore anything)");
+
+        assert!(resolution.diagnostics.is_empty());
+    }
+}
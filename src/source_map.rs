@@ -0,0 +1,180 @@
+use crate::span::Position;
+
+/// A point in the generated code that came from `source`.
+/// `name` is the original identifier, for mappings of identifiers.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Mapping {
+    /// 0-based, like `Position::column`.
+    pub generated_line: usize,
+    pub generated_column: usize,
+    pub source: Position,
+    pub name: Option<String>,
+}
+
+/// A Source Map v3 for code generated from a single source.
+/// Columns count chars on both sides, which are UTF-16 code units for text in the Basic Multilingual Plane.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceMap {
+    pub sources: Vec<String>,
+    pub sources_content: Vec<Option<String>>,
+    pub names: Vec<String>,
+    pub mappings: String,
+}
+
+impl SourceMap {
+    /// `mappings` must be in generated order.
+    pub fn new(source: &str, source_content: Option<&str>, mappings: &[Mapping]) -> SourceMap {
+        let mut names: Vec<String> = vec![];
+        let mut encoded = String::new();
+
+        let mut line = 0;
+        // Every field but the generated column is relative to the previous segment of the whole map.
+        let mut previous_column: i64 = 0;
+        let mut previous_source_line: i64 = 0;
+        let mut previous_source_column: i64 = 0;
+        let mut previous_name: i64 = 0;
+        for (index, mapping) in mappings.iter().enumerate() {
+            if index > 0 && mapping.generated_line == line {
+                encoded.push(',');
+            }
+            while line < mapping.generated_line {
+                encoded.push(';');
+                line += 1;
+                previous_column = 0;
+            }
+
+            let source_line = mapping.source.line.saturating_sub(1) as i64;
+            let source_column = mapping.source.column as i64;
+            encode_vlq(mapping.generated_column as i64 - previous_column, &mut encoded);
+            encode_vlq(0, &mut encoded);
+            encode_vlq(source_line - previous_source_line, &mut encoded);
+            encode_vlq(source_column - previous_source_column, &mut encoded);
+            previous_column = mapping.generated_column as i64;
+            previous_source_line = source_line;
+            previous_source_column = source_column;
+
+            if let Some(name) = &mapping.name {
+                let name_index = match names.iter().position(|known| known == name) {
+                    Some(name_index) => name_index,
+                    None => {
+                        names.push(name.clone());
+                        names.len() - 1
+                    }
+                } as i64;
+                encode_vlq(name_index - previous_name, &mut encoded);
+                previous_name = name_index;
+            }
+        }
+
+        SourceMap {
+            sources: vec![source.to_string()],
+            sources_content: vec![source_content.map(|content| content.to_string())],
+            names,
+            mappings: encoded,
+        }
+    }
+
+    /// Written by hand so source maps do not need the `serde` feature.
+    pub fn to_json(&self) -> String {
+        let sources = self.sources.iter().map(|source| json_string(source)).collect::<Vec<String>>();
+        let sources_content = self.sources_content.iter()
+            .map(|content| content.as_deref().map_or_else(|| "null".to_string(), json_string))
+            .collect::<Vec<String>>();
+        let names = self.names.iter().map(|name| json_string(name)).collect::<Vec<String>>();
+
+        format!(
+            "{{\"version\":3,\"sources\":[{}],\"sourcesContent\":[{}],\"names\":[{}],\"mappings\":{}}}",
+            sources.join(","),
+            sources_content.join(","),
+            names.join(","),
+            json_string(&self.mappings),
+        )
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Base64 VLQ: the sign goes in the lowest bit, then 5 bits per digit with bit 6 marking a continuation.
+fn encode_vlq(value: i64, out: &mut String) {
+    let mut vlq = if value < 0 { (value.unsigned_abs() << 1) | 1 } else { (value as u64) << 1 };
+    loop {
+        let mut digit = (vlq & 0b11111) as usize;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if ch.is_control() => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+
+    json
+}
+
+#[cfg(test)]
+mod source_map_tests {
+    use crate::source_map::{encode_vlq, json_string, Mapping, SourceMap};
+    use crate::span::Position;
+
+    fn vlq(value: i64) -> String {
+        let mut out = String::new();
+        encode_vlq(value, &mut out);
+
+        out
+    }
+
+    fn mapping(generated_line: usize, generated_column: usize, line: usize, column: usize, name: Option<&str>) -> Mapping {
+        Mapping {
+            generated_line,
+            generated_column,
+            source: Position { offset: 0, line, column },
+            name: name.map(|name| name.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_encode_vlq() {
+        assert_eq!(vlq(0), "A");
+        assert_eq!(vlq(1), "C");
+        assert_eq!(vlq(-1), "D");
+        assert_eq!(vlq(15), "e");
+        assert_eq!(vlq(16), "gB");
+        assert_eq!(vlq(-1000), "x+B");
+    }
+
+    #[test]
+    fn test_source_map() {
+        let source_map = SourceMap::new("input.lisp", Some("(add 1\n 2)"), &[
+            mapping(0, 0, 1, 1, Some("add")),
+            mapping(0, 3, 1, 0, None),
+            mapping(1, 0, 2, 1, None),
+            mapping(1, 4, 1, 1, Some("add")),
+        ]);
+
+        assert_eq!(source_map.names, vec!["add"]);
+        assert_eq!(source_map.mappings, "AAACA,GAAD;AACC,IADAA");
+        assert_eq!(source_map.to_json(), "{\"version\":3,\"sources\":[\"input.lisp\"],\"sourcesContent\":[\"(add 1\\n 2)\"],\"names\":[\"add\"],\"mappings\":\"AAACA,GAAD;AACC,IADAA\"}");
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\c\u{1}"), "\"a\\\"b\\\\c\\u0001\"");
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A location in the source code. `offset` counts chars from the start of the input,
+/// `line` is 1-based and `column` is 0-based, as in ESTree and Source Map positions.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Formats as `line:column` with a 1-based column, the way editors and compilers report locations.
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column + 1)
+    }
+}
+
+/// The source range a token or node was read from. The end is exclusive.
+/// Nodes built by hand or read from JSON without positions have an empty default span.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span { start, end }
+    }
+
+    /// From the start of `self` to the end of `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span { start: self.start, end: other.end }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.offset == self.end.offset
+    }
+}
+
+/// Converts char offsets of one input into positions.
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(chars: &[char]) -> LineIndex {
+        let mut line_starts = vec![0];
+        for (offset, ch) in chars.iter().enumerate() {
+            if *ch == '\n' {
+                line_starts.push(offset + 1);
+            }
+        }
+
+        LineIndex { line_starts }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|line_start| *line_start <= offset);
+
+        Position {
+            offset,
+            line,
+            column: offset - self.line_starts[line - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod span_tests {
+    use crate::span::{LineIndex, Position};
+
+    #[test]
+    fn test_line_index_position() {
+        let chars = "(add 1\n  2)\n".chars().collect::<Vec<char>>();
+
+        let line_index = LineIndex::new(&chars);
+
+        assert_eq!(line_index.position(0), Position { offset: 0, line: 1, column: 0 });
+        assert_eq!(line_index.position(6), Position { offset: 6, line: 1, column: 6 });
+        assert_eq!(line_index.position(9), Position { offset: 9, line: 2, column: 2 });
+        assert_eq!(line_index.position(12), Position { offset: 12, line: 3, column: 0 });
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use crate::span::Span;
+
+/// At most this many names are suggested for one mistake.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// A fix that replaces the text at `span` with `replacement`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Suggestion {
+    /// Applies the fix to `source`, the code `span` was read from.
+    pub fn apply(&self, source: &str) -> String {
+        let mut chars = source.chars();
+        let mut fixed: String = chars.by_ref().take(self.span.start.offset).collect();
+        fixed.push_str(&self.replacement);
+        fixed.extend(chars.skip(self.span.end.offset - self.span.start.offset));
+
+        fixed
+    }
+}
+
+/// Alternative fixes for one mistake, nearest first.
+/// Formats as ` (did you mean a or b?)`, or nothing when there is none, to end an error message.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Suggestions(pub Vec<Suggestion>);
+
+impl Suggestions {
+    /// Suggests replacing `name`, read from `span`, with the nearest `candidates` by edit distance.
+    /// Candidates further than a third of the length of `name` are not suggested.
+    pub fn for_name<'a>(name: &str, span: Span, candidates: impl IntoIterator<Item = &'a str>) -> Suggestions {
+        let max_distance = (name.chars().count() / 3).max(1);
+        let mut nearest = candidates.into_iter()
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect::<Vec<(usize, &str)>>();
+        nearest.sort();
+        if let Some((closest, _)) = nearest.first().copied() {
+            nearest.retain(|(distance, _)| *distance == closest);
+        }
+        nearest.dedup();
+        nearest.truncate(MAX_SUGGESTIONS);
+
+        Suggestions(nearest.into_iter().map(|(_, candidate)| Suggestion { span, replacement: candidate.to_string() }).collect())
+    }
+}
+
+impl fmt::Display for Suggestions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = self.0.iter().map(|suggestion| suggestion.replacement.as_str()).collect::<Vec<&str>>();
+        match names.split_last() {
+            None => Ok(()),
+            Some((last, [])) => write!(f, " (did you mean {}?)", last),
+            Some((last, rest)) => write!(f, " (did you mean {} or {}?)", rest.join(", "), last),
+        }
+    }
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions
+/// and transpositions of adjacent chars each count as one edit.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+
+    // distances[i][j] is the distance between the first i chars of a and the first j chars of b.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod suggest_tests {
+    use crate::span::{Position, Span};
+    use crate::suggest::{edit_distance, Suggestion, Suggestions};
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(Position { offset: start, line: 1, column: start }, Position { offset: end, line: 1, column: end })
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("substract", "subtract"), 1);
+        assert_eq!(edit_distance("ad", "add"), 1);
+        assert_eq!(edit_distance("mutliply", "multiply"), 1);
+        assert_eq!(edit_distance("print", "concat"), 5);
+        assert_eq!(edit_distance("", "eq"), 2);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let builtins = ["add", "subtract", "lt", "le", "gt", "print"];
+
+        let suggestions = Suggestions::for_name("substract", span(1, 10), builtins);
+        assert_eq!(suggestions.0, vec![Suggestion { span: span(1, 10), replacement: "subtract".to_string() }]);
+        assert_eq!(suggestions.to_string(), " (did you mean subtract?)");
+
+        assert_eq!(Suggestions::for_name("lq", span(1, 3), builtins).to_string(), " (did you mean le or lt?)");
+        assert_eq!(Suggestions::for_name("fullName", span(1, 9), builtins).to_string(), "");
+    }
+
+    #[test]
+    fn test_apply_suggestion() {
+        let suggestion = Suggestion { span: span(3, 12), replacement: "subtract".to_string() };
+
+        assert_eq!(suggestion.apply("(é substract 1 2)"), "(é subtract 1 2)");
+    }
+}
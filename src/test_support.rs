@@ -0,0 +1,55 @@
+//! Builders for the trees unit tests start from. Every node gets an empty span.
+
+/// Source AST nodes, as the parser builds them.
+pub mod ast {
+    use std::rc::Rc;
+    use crate::ast::{ASTNode, ASTNodeType, CallExpression, NumberLiteral, Program, StringLiteral};
+    use crate::span::Span;
+
+    pub fn number(value: &str) -> Rc<ASTNode> {
+        Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: value.to_string() }))
+    }
+
+    pub fn string(value: &str) -> Rc<ASTNode> {
+        Rc::new(ASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: value.to_string() }))
+    }
+
+    pub fn call(value: &str, params: Vec<Rc<ASTNode>>) -> Rc<ASTNode> {
+        Rc::new(ASTNode::CallExpression(CallExpression { node_type: ASTNodeType::CallExpression, span: Span::default(), value: value.to_string(), callee_span: Span::default(), params }))
+    }
+
+    pub fn program(body: Vec<Rc<ASTNode>>) -> Program {
+        Program { node_type: ASTNodeType::Program, span: Span::default(), body }
+    }
+}
+
+/// Transformed AST nodes, as the transformer builds them.
+pub mod new_ast {
+    use crate::ast::{ASTNodeType, CallExpressionWithCallee, ExpressionStatement, Identifier, NewASTNode, NewProgram, NumberLiteral, StringLiteral};
+    use crate::span::Span;
+
+    pub fn number(value: &str) -> NewASTNode {
+        NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: value.to_string() })
+    }
+
+    pub fn string(value: &str) -> NewASTNode {
+        NewASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: value.to_string() })
+    }
+
+    pub fn call(name: &str, arguments: Vec<NewASTNode>) -> NewASTNode {
+        NewASTNode::CallExpressionWithCallee(CallExpressionWithCallee {
+            node_type: ASTNodeType::CallExpression,
+            span: Span::default(),
+            callee: Identifier { node_type: ASTNodeType::Identifier, span: Span::default(), name: name.to_string() },
+            arguments,
+        })
+    }
+
+    pub fn statement(expression: NewASTNode) -> NewASTNode {
+        NewASTNode::ExpressionStatement(ExpressionStatement { node_type: ASTNodeType::ExpressionStatement, span: Span::default(), expression: Box::new(expression) })
+    }
+
+    pub fn program(body: Vec<NewASTNode>) -> NewASTNode {
+        NewASTNode::NewProgram(NewProgram { node_type: ASTNodeType::Program, span: Span::default(), body })
+    }
+}
@@ -1,9 +1,13 @@
+use crate::span::Span;
 use strum_macros::Display;
 use strum_macros::EnumIter;
 use strum_macros::EnumString;
 use strum_macros::IntoStaticStr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Clone, Copy, EnumString, Display, IntoStaticStr, EnumIter)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
 pub enum TokenType {
     #[strum(serialize = "number")]
     NUMBER,
@@ -13,10 +17,17 @@ pub enum TokenType {
     NAME,
     #[strum(serialize = "paren")]
     PAREN,
+    /// `` ` ``, `,` or `,@`: quasiquote, unquote or unquote-splicing the next form.
+    #[strum(serialize = "quote")]
+    QUOTE,
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Token {
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
     pub(crate) token_type: TokenType,
     pub(crate) value: String,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Span::is_empty"))]
+    pub(crate) span: Span,
 }
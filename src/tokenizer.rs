@@ -1,3 +1,4 @@
+use crate::span::{LineIndex, Position, Span};
 use crate::token::{Token, TokenType};
 use regex::Regex;
 use thiserror::Error;
@@ -6,13 +7,17 @@ pub fn tokenizer(input: String) -> anyhow::Result<Vec<Token>> {
     let whitespace: Regex = Regex::new(r"(\s|\r\n|\n|\r)").unwrap();
     let numbers: Regex = Regex::new(r"[0-9]").unwrap();
     let quotes: Regex = Regex::new(r#"(["'])"#).unwrap();
-    let letters: Regex = Regex::new(r"[a-zA-Z_-]").unwrap();
+    let letters: Regex = Regex::new(r"[a-zA-Z_&-]").unwrap();
 
     let mut current: usize = 0;
     let mut tokens: Vec<Token> = vec![];
 
     let input_chars: Vec<char> = input.chars().collect::<Vec<char>>();
     let input_chars_length: usize = input_chars.len();
+    let line_index = LineIndex::new(&input_chars);
+    let span = |start: usize, end: usize| -> Span {
+        Span::new(line_index.position(start), line_index.position(end))
+    };
 
     let get_char = |current: usize| -> char {
         return input_chars[current];
@@ -48,15 +53,35 @@ pub fn tokenizer(input: String) -> anyhow::Result<Vec<Token>> {
             let token = Token {
                 token_type: TokenType::PAREN,
                 value: ch.to_string(),
+                span: span(current, current + 1),
             };
             tokens.push(token);
             consume_char(&mut current);
             continue;
         }
 
+        // quasiquote, unquote, unquote-splicing
+        let ch = get_char(current);
+        if ch == '`' || ch == ',' {
+            let start = current;
+            let mut value = consume_char(&mut current).to_string();
+            if ch == ',' && is_eos(current) && get_char(current) == '@' {
+                value.push(consume_char(&mut current));
+            }
+
+            let token = Token {
+                token_type: TokenType::QUOTE,
+                value,
+                span: span(start, current),
+            };
+            tokens.push(token);
+            continue;
+        }
+
         // number
         let ch = get_char(current);
         if numbers.is_match(&ch.to_string()) {
+            let start = current;
             let mut value: String = String::from("");
             while is_eos(current) {
                 let ch = get_char(current);
@@ -71,6 +96,7 @@ pub fn tokenizer(input: String) -> anyhow::Result<Vec<Token>> {
             let token = Token {
                 token_type: TokenType::NUMBER,
                 value,
+                span: span(start, current),
             };
             tokens.push(token);
             continue;
@@ -79,6 +105,7 @@ pub fn tokenizer(input: String) -> anyhow::Result<Vec<Token>> {
         // string
         let ch = get_char(current);
         if quotes.is_match(&ch.to_string()) {
+            let start = current;
             consume_char(&mut current);
 
             let mut value: String = String::from("");
@@ -91,11 +118,15 @@ pub fn tokenizer(input: String) -> anyhow::Result<Vec<Token>> {
                     break;
                 }
             }
+            if !is_eos(current) {
+                return Err(TokenizeError::UnterminatedString(line_index.position(start)).into());
+            }
             consume_char(&mut current);
 
             let token = Token {
                 token_type: TokenType::STRING,
                 value,
+                span: span(start, current),
             };
             tokens.push(token);
             continue;
@@ -104,6 +135,7 @@ pub fn tokenizer(input: String) -> anyhow::Result<Vec<Token>> {
         // letters
         let ch = get_char(current);
         if letters.is_match(&ch.to_string()) {
+            let start = current;
             let mut value: String = String::from("");
             while is_eos(current) {
                 let ch = get_char(current);
@@ -118,6 +150,7 @@ pub fn tokenizer(input: String) -> anyhow::Result<Vec<Token>> {
             let token = Token {
                 token_type: TokenType::NAME,
                 value,
+                span: span(start, current),
             };
             tokens.push(token);
             continue;
@@ -131,11 +164,14 @@ pub fn tokenizer(input: String) -> anyhow::Result<Vec<Token>> {
 #[derive(Debug, Error)]
 pub enum TokenizeError {
     #[error("Error: Unknown character: {0}")]
-    UnknownCharacter(String)
+    UnknownCharacter(String),
+    #[error("Error: Unterminated string at {0}")]
+    UnterminatedString(Position),
 }
 
 #[cfg(test)]
 mod tokenizer_tests {
+    use crate::span::Span;
     use crate::tokenizer::{tokenizer, TokenizeError};
     use thiserror::Error;
     use crate::token::{Token, TokenType};
@@ -148,28 +184,76 @@ mod tokenizer_tests {
         ";
 
         let tokenize_result = tokenizer(code.to_string());
-        let result_tokens = tokenize_result.unwrap();
+        let result_tokens = tokenize_result.unwrap()
+            .into_iter()
+            .map(|token| Token { span: Span::default(), ..token })
+            .collect::<Vec<Token>>();
 
         let expected_tokens = vec![
-            Token { token_type: TokenType::PAREN, value: "(".to_string() },
-            Token { token_type: TokenType::NAME, value: "add".to_string() },
-            Token { token_type: TokenType::NUMBER, value: "2".to_string() },
-            Token { token_type: TokenType::PAREN, value: "(".to_string() },
-            Token { token_type: TokenType::NAME, value: "subtract".to_string() },
-            Token { token_type: TokenType::NUMBER, value: "4".to_string() },
-            Token { token_type: TokenType::NUMBER, value: "2".to_string() },
-            Token { token_type: TokenType::PAREN, value: ")".to_string() },
-            Token { token_type: TokenType::PAREN, value: ")".to_string() },
-            Token { token_type: TokenType::PAREN, value: "(".to_string() },
-            Token { token_type: TokenType::NAME, value: "fullName".to_string() },
-            Token { token_type: TokenType::STRING, value: "hoge".to_string() },
-            Token { token_type: TokenType::STRING, value: "foo".to_string() },
-            Token { token_type: TokenType::PAREN, value: ")".to_string() },
+            Token { token_type: TokenType::PAREN, value: "(".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NAME, value: "add".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NUMBER, value: "2".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: "(".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NAME, value: "subtract".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NUMBER, value: "4".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NUMBER, value: "2".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: ")".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: ")".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: "(".to_string(), span: Span::default() },
+            Token { token_type: TokenType::NAME, value: "fullName".to_string(), span: Span::default() },
+            Token { token_type: TokenType::STRING, value: "hoge".to_string(), span: Span::default() },
+            Token { token_type: TokenType::STRING, value: "foo".to_string(), span: Span::default() },
+            Token { token_type: TokenType::PAREN, value: ")".to_string(), span: Span::default() },
         ];
 
         assert_eq!(result_tokens, expected_tokens);
     }
 
+    #[test]
+    fn test_tokenize_spans() {
+        use crate::span::Position;
+
+        let code = "(add 12\n  'hoge')";
+
+        let tokenize_result = tokenizer(code.to_string());
+        let result_spans = tokenize_result.unwrap()
+            .iter()
+            .map(|token| (token.span.start, token.span.end))
+            .collect::<Vec<(Position, Position)>>();
+
+        let position = |offset: usize, line: usize, column: usize| Position { offset, line, column };
+        let expected_spans = vec![
+            (position(0, 1, 0), position(1, 1, 1)),
+            (position(1, 1, 1), position(4, 1, 4)),
+            (position(5, 1, 5), position(7, 1, 7)),
+            (position(10, 2, 2), position(16, 2, 8)),
+            (position(16, 2, 8), position(17, 2, 9)),
+        ];
+
+        assert_eq!(result_spans, expected_spans);
+    }
+
+    #[test]
+    fn test_tokenize_quotes() {
+        let tokens = tokenizer("`(print ,x ,@rest)".to_string()).unwrap();
+
+        let result_tokens = tokens.iter()
+            .map(|token| (token.token_type, token.value.as_str()))
+            .collect::<Vec<(TokenType, &str)>>();
+
+        assert_eq!(result_tokens, vec![
+            (TokenType::QUOTE, "`"),
+            (TokenType::PAREN, "("),
+            (TokenType::NAME, "print"),
+            (TokenType::QUOTE, ","),
+            (TokenType::NAME, "x"),
+            (TokenType::QUOTE, ",@"),
+            (TokenType::NAME, "rest"),
+            (TokenType::PAREN, ")"),
+        ]);
+        assert_eq!((tokens[5].span.start.offset, tokens[5].span.end.offset), (11, 13));
+    }
+
     #[test]
     fn test_tokenize_unknown_character() {
         let code = "*";
@@ -182,4 +266,13 @@ mod tokenizer_tests {
 
         assert_eq!(format!("{}", route_cause), "Error: Unknown character: *");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_tokenize_unterminated_string() {
+        let code = "(print\n  'hoge)";
+
+        let result_error = tokenizer(code.to_string()).unwrap_err();
+
+        assert_eq!(format!("{}", result_error.root_cause()), "Error: Unterminated string at 2:3");
+    }
+}
@@ -1,26 +1,127 @@
-use crate::ast::{ASTNodeType, NewProgram, Program};
-use crate::traverser::{TransformError, traverser};
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+use crate::ast::{ASTNodeType, CallExpression, CallExpressionWithCallee, ExpressionStatement, Identifier, NewASTNode, NewProgram, Program};
+use crate::functions::FunctionRegistry;
+use crate::suggest::Suggestions;
+use crate::traverser::{TransformError, traverser, Visitors};
+use crate::visitor::{CallExpressionVisitFn, NumberLiteralVisitFn, StringLiteralVisitFn};
 
-pub fn transformer(program: Program) -> anyhow::Result<(), ()>{
-    let mut new_ast =  NewProgram{
+pub fn transformer(program: Program) -> anyhow::Result<NewProgram, TransformError> {
+    transformer_with_functions(program, &FunctionRegistry::with_builtins(), "js")
+}
+
+/// Checks the arity of calls to the functions in `functions`, rejects calls to others when it is strict,
+/// and calls registered functions by their name in `target`.
+pub fn transformer_with_functions(program: Program, functions: &FunctionRegistry, target: &str) -> anyhow::Result<NewProgram, TransformError> {
+    let span = program.span;
+    let context = Rc::new(RefCell::new(TransformContext::new(functions.clone(), target)));
+
+    let mut visitors: Visitors = Visitors::new();
+    visitors.insert(ASTNodeType::NumberLiteral, Box::new(NumberLiteralVisitFn { context: context.clone() }));
+    visitors.insert(ASTNodeType::StringLiteral, Box::new(StringLiteralVisitFn { context: context.clone() }));
+    visitors.insert(ASTNodeType::CallExpression, Box::new(CallExpressionVisitFn { context: context.clone() }));
+
+    traverser(program, &visitors)?;
+    if let Some(error) = context.borrow_mut().error.take() {
+        return Err(error);
+    }
+
+    let new_ast = NewProgram {
         node_type: ASTNodeType::Program,
-        body: vec![]
+        span,
+        body: mem::take(&mut context.borrow_mut().body),
     };
 
-    let traverse_result = traverser(
-        program,
-    );
+    Ok(new_ast)
+}
+
+/// The part of the new AST built so far.
+/// Call expressions stay in `open_calls` until the traversal exits them.
+/// Visitors cannot fail, so the first error is kept in `error` and the traversal goes on.
+#[derive(Default)]
+pub struct TransformContext {
+    body: Vec<NewASTNode>,
+    open_calls: Vec<CallExpressionWithCallee>,
+    functions: FunctionRegistry,
+    target: String,
+    error: Option<TransformError>,
+}
+
+impl TransformContext {
+    pub fn new(functions: FunctionRegistry, target: &str) -> TransformContext {
+        TransformContext { functions, target: target.to_string(), ..TransformContext::default() }
+    }
+
+    fn fail(&mut self, error: TransformError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    /// Adds a node to the innermost open call, or to the program body at the top level.
+    pub fn push(&mut self, node: NewASTNode) {
+        match self.open_calls.last_mut() {
+            Some(call_expression) => call_expression.arguments.push(node),
+            None => self.body.push(node),
+        }
+    }
+
+    pub fn open_call(&mut self, call_expression: &CallExpression) {
+        let name = &call_expression.value;
+        let callee_span = call_expression.callee_span;
+        let position = callee_span.start;
+        let argc = call_expression.params.len();
+        let (target_name, error) = match self.functions.get(name) {
+            Some(function) => (
+                function.target_name(&self.target).to_string(),
+                (!function.arity.accepts(argc)).then(|| TransformError::ArityMismatch(name.clone(), function.arity, argc, position)),
+            ),
+            None => (name.clone(), self.functions.strict.then(|| {
+                TransformError::UnknownFunction(name.clone(), position, Suggestions::for_name(name, callee_span, self.functions.names()))
+            })),
+        };
+        if let Some(error) = error {
+            self.fail(error);
+        }
+
+        self.open_calls.push(CallExpressionWithCallee {
+            node_type: ASTNodeType::CallExpression,
+            span: call_expression.span,
+            callee: Identifier {
+                node_type: ASTNodeType::Identifier,
+                span: call_expression.callee_span,
+                name: target_name,
+            },
+            arguments: vec![],
+        });
+    }
+
+    /// Top-level calls are wrapped in an `ExpressionStatement`.
+    pub fn close_call(&mut self) {
+        let Some(call_expression) = self.open_calls.pop() else {
+            return;
+        };
 
-    match traverse_result {
-        Ok(_) => return Ok(()),
-        _ => Err(())
+        let span = call_expression.span;
+        let node = NewASTNode::CallExpressionWithCallee(call_expression);
+        if self.open_calls.is_empty() {
+            self.body.push(NewASTNode::ExpressionStatement(ExpressionStatement {
+                node_type: ASTNodeType::ExpressionStatement,
+                span,
+                expression: Box::new(node),
+            }));
+        } else {
+            self.push(node);
+        }
     }
 }
 
 #[cfg(test)]
 mod transformer_tests {
+    use crate::span::Span;
     use std::rc::Rc;
-    use crate::ast::{ASTNode, ASTNodeType, NumberLiteral, Program};
+    use crate::ast::{ASTNode, ASTNodeType, NewASTNode, NumberLiteral, Program};
     use crate::transformer::transformer;
 
     #[test]
@@ -29,16 +130,17 @@ mod transformer_tests {
 
         let ctx = mock_array_traverser::traverse_array_context();
         ctx.expect()
-            .returning(|_, _, _| Ok(()));
+            .returning(|_, _, _, _| Ok(()));
 
-        let param_ast_node1 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, value: "number_literal1".to_string() };
+        let param_ast_node1 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "number_literal1".to_string() };
         let param_ast_node_rc1 = Rc::new(ASTNode::NumberLiteral(param_ast_node1));
 
-        let param_ast_node2 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, value: "number_literal2".to_string() };
+        let param_ast_node2 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "number_literal2".to_string() };
         let param_ast_node_rc2 = Rc::new(ASTNode::NumberLiteral(param_ast_node2));
 
         let program = Program {
             node_type: ASTNodeType::Program,
+            span: Span::default(),
             body: vec![
                 param_ast_node_rc1,
                 param_ast_node_rc2,
@@ -47,5 +149,81 @@ mod transformer_tests {
         // let program_rc = Rc::new(ASTNode::Program(program));
 
         let transform_result = transformer(program);
+        assert!(transform_result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_transform_context_builds_new_program() {
+        use crate::ast::{ASTNode, CallExpression, StringLiteral};
+        use crate::transformer::TransformContext;
+        use crate::visitor::{CallExpressionVisitFn, NumberLiteralVisitFn, StringLiteralVisitFn, Visitor};
+        use std::cell::RefCell;
+
+        let context = Rc::new(RefCell::new(TransformContext::default()));
+        let call_visitor = CallExpressionVisitFn { context: context.clone() };
+        let number_visitor = NumberLiteralVisitFn { context: context.clone() };
+        let string_visitor = StringLiteralVisitFn { context: context.clone() };
+
+        let add = ASTNode::CallExpression(CallExpression { node_type: ASTNodeType::CallExpression, span: Span::default(), value: "add".to_string(), callee_span: Span::default(), params: vec![] });
+        let subtract = ASTNode::CallExpression(CallExpression { node_type: ASTNodeType::CallExpression, span: Span::default(), value: "subtract".to_string(), callee_span: Span::default(), params: vec![] });
+        let two = ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "2".to_string() });
+        let hoge = ASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "hoge".to_string() });
+
+        // the order traverser calls the visitors in for `(add 2 (subtract 'hoge')) 2`
+        call_visitor.enter(&add, None, &[]);
+        number_visitor.enter(&two, None, &[]);
+        call_visitor.enter(&subtract, None, &[]);
+        string_visitor.enter(&hoge, None, &[]);
+        call_visitor.exit(&subtract, None, &[]);
+        call_visitor.exit(&add, None, &[]);
+        number_visitor.enter(&two, None, &[]);
+
+        let context = context.borrow();
+        assert_eq!(context.body.len(), 2);
+
+        let NewASTNode::ExpressionStatement(statement) = &context.body[0] else {
+            panic!("top-level call is not wrapped in a statement");
+        };
+        let NewASTNode::CallExpressionWithCallee(add) = statement.expression.as_ref() else {
+            panic!("statement does not hold the call");
+        };
+        assert_eq!(add.callee.name, "add");
+        assert_eq!(add.arguments.len(), 2);
+        assert!(matches!(&add.arguments[1], NewASTNode::CallExpressionWithCallee(subtract) if subtract.callee.name == "subtract" && subtract.arguments.len() == 1));
+        assert!(matches!(&context.body[1], NewASTNode::NumberLiteral(n) if n.value == "2"));
+    }
+
+    #[test]
+    fn test_transform_context_checks_calls() {
+        use crate::ast::{ASTNode, CallExpression};
+        use crate::functions::{Arity, Function, FunctionRegistry};
+        use crate::span::Position;
+        use crate::transformer::TransformContext;
+        use crate::traverser::TransformError;
+
+        let call = |name: &str, argc: usize| CallExpression {
+            node_type: ASTNodeType::CallExpression,
+            span: Span::default(),
+            value: name.to_string(),
+            callee_span: Span::new(Position { offset: 1, line: 1, column: 1 }, Position { offset: 4, line: 1, column: 4 }),
+            params: (0..argc).map(|_| Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "1".to_string() }))).collect(),
+        };
+
+        let mut functions = FunctionRegistry::with_builtins();
+        functions.register(Function::new("fullName", Arity::exactly(2)).with_target_name("python", "full_name"));
+        let mut context = TransformContext::new(functions.clone(), "python");
+        context.open_call(&call("fullName", 2));
+        context.open_call(&call("subtract", 4));
+        context.open_call(&call("add", 0));
+
+        assert_eq!(context.open_calls[0].callee.name, "full_name");
+        assert!(matches!(&context.error, Some(TransformError::ArityMismatch(name, arity, 4, _)) if name == "subtract" && *arity == Arity::exactly(2)));
+        assert_eq!(context.error.as_ref().unwrap().to_string(), "Error: subtract expects 2 arguments but got 4 at 1:2");
+
+        functions.strict = true;
+        let mut context = TransformContext::new(functions, "js");
+        context.open_call(&call("lastName", 1));
+
+        assert_eq!(context.error.as_ref().unwrap().to_string(), "Error: Unknown function: lastName at 1:2");
+    }
+}
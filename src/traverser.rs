@@ -1,60 +1,58 @@
 use std::collections::HashMap;
 use std::rc::Rc;
-use crate::ast::{ASTNode, ASTNodeType, Program, Root};
-use crate::visitor::{CallExpressionVisitFn, NumberLiteralVisitFn, ProgramVisitFn, StringLiteralVisitFn, Visitor};
+use crate::ast::{ASTNode, ASTNodeType, NewASTNode, Program};
+use crate::functions::Arity;
+use crate::span::Position;
+use crate::suggest::Suggestions;
+use crate::visitor::{Ancestor, NewVisitor, Visitor};
 use thiserror::Error;
 use mockall_double::double;
 #[cfg(test)]
 use mockall::automock;
 
 pub type Visitors = HashMap<ASTNodeType, Box<dyn Visitor>>;
+pub type NewVisitors = HashMap<ASTNodeType, Box<dyn NewVisitor>>;
 
-pub fn traverser(program: Program) -> anyhow::Result<(), TransformError> {
-    let mut visitor: Visitors = Visitors::new();
-
-    visitor.insert(ASTNodeType::Program, Box::new(ProgramVisitFn));
-    visitor.insert(ASTNodeType::NumberLiteral, Box::new(NumberLiteralVisitFn));
-    visitor.insert(ASTNodeType::StringLiteral, Box::new(StringLiteralVisitFn));
-    visitor.insert(ASTNodeType::CallExpression, Box::new(CallExpressionVisitFn));
-
-    let root = Rc::new(ASTNode::Root(Root { node_type: ASTNodeType::Root }));
+pub fn traverser(program: Program, visitors: &Visitors) -> anyhow::Result<(), TransformError> {
     let program_rc = Rc::new(ASTNode::Program(program));
-    let traverse_result = travers_node(&visitor, program_rc, root);
+    let mut path: Vec<Ancestor> = vec![];
+    let traverse_result = travers_node(visitors, program_rc, &mut path);
 
     return traverse_result;
 }
 
-pub fn travers_node(visitors: &Visitors, node: Rc<ASTNode>, parent: Rc<ASTNode>) -> anyhow::Result<(), TransformError> {
+/// `path` holds the ancestors of `node`, root first; the last entry is its parent.
+/// This recurses once per level; trees coming from `parser` are bounded by its max depth.
+pub fn travers_node(visitors: &Visitors, node: Rc<ASTNode>, path: &mut Vec<Ancestor>) -> anyhow::Result<(), TransformError> {
     #[double]
     use array_traverser as inner;
 
     let visitor = visitors.get(&node.get_node_type());
+    let parent = path.last().map(|ancestor| ancestor.node.clone());
 
     if let Some(methods) = visitor {
-        methods.enter(&node, parent.clone())
+        methods.enter(&node, parent.clone(), path)
     }
 
     let node_ref = node.as_ref();
     let traverse_child_result = match node_ref {
         ASTNode::Program(program) => {
-            let body = (*program).body.clone();
-            inner::traverse_array(visitors, body, node.clone())
+            let body = program.body.clone();
+            inner::traverse_array(visitors, body, node.clone(), path)
         }
         ASTNode::CallExpression(call_expression) => {
-            let params = (*call_expression).params.clone();
-            inner::traverse_array(visitors, params, node.clone())
+            let params = call_expression.params.clone();
+            inner::traverse_array(visitors, params, node.clone(), path)
         }
         ASTNode::NumberLiteral(_) => Ok(()),
         ASTNode::StringLiteral(_) => Ok(()),
-        _unknown_node => Err(TransformError::NoTransformTargetNode()).into()
+        _unknown_node => Err(TransformError::NoTransformTargetNode())
     };
 
-    if let Err(_) = traverse_child_result {
-        return traverse_child_result;
-    }
+    traverse_child_result?;
 
     if let Some(methods) = visitor {
-        methods.exit(&node, parent.clone())
+        methods.exit(&node, parent, path)
     }
 
     Ok(())
@@ -64,34 +62,79 @@ pub fn travers_node(visitors: &Visitors, node: Rc<ASTNode>, parent: Rc<ASTNode>)
 pub(super) mod array_traverser {
     use std::rc::Rc;
     use crate::ast::ASTNode;
+    use crate::visitor::Ancestor;
     use super::{TransformError, travers_node, Visitors};
 
-    pub fn traverse_array(visitors: &Visitors, array: Vec<Rc<ASTNode>>, parent: Rc<ASTNode>) -> anyhow::Result<(), TransformError> {
-        for node in array {
-            let traverse_result = travers_node(visitors, node, parent.clone());
-            if let Err(_) = traverse_result {
-                return traverse_result;
-            }
+    pub fn traverse_array(visitors: &Visitors, array: Vec<Rc<ASTNode>>, parent: Rc<ASTNode>, path: &mut Vec<Ancestor>) -> anyhow::Result<(), TransformError> {
+        for (index, node) in array.into_iter().enumerate() {
+            path.push(Ancestor { node: parent.clone(), index });
+            let traverse_result = travers_node(visitors, node, path);
+            path.pop();
+
+            traverse_result?;
         }
 
         Ok(())
     }
 }
 
+pub fn new_traverser(new_program: &NewASTNode, visitors: &NewVisitors) -> anyhow::Result<(), TransformError> {
+    travers_new_node(visitors, new_program, None)
+}
+
+pub fn travers_new_node(visitors: &NewVisitors, node: &NewASTNode, parent: Option<&NewASTNode>) -> anyhow::Result<(), TransformError> {
+    let visitor = visitors.get(&node.get_node_type());
+
+    if let Some(methods) = visitor {
+        methods.enter(node, parent)
+    }
+
+    match node {
+        NewASTNode::NewProgram(new_program) => {
+            for child in &new_program.body {
+                travers_new_node(visitors, child, Some(node))?;
+            }
+        }
+        NewASTNode::ExpressionStatement(expression_statement) => {
+            travers_new_node(visitors, &expression_statement.expression, Some(node))?;
+        }
+        NewASTNode::CallExpressionWithCallee(call_expression) => {
+            travers_new_node(visitors, &NewASTNode::Identifier(call_expression.callee.clone()), Some(node))?;
+            for argument in &call_expression.arguments {
+                travers_new_node(visitors, argument, Some(node))?;
+            }
+        }
+        NewASTNode::Identifier(_) => {}
+        NewASTNode::NumberLiteral(_) => {}
+        NewASTNode::StringLiteral(_) => {}
+    }
+
+    if let Some(methods) = visitor {
+        methods.exit(node, parent)
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Error)]
 pub enum TransformError {
     #[error("Error: The node is not transform target")]
-    NoTransformTargetNode()
+    NoTransformTargetNode(),
+    #[error("Error: Unknown function: {0} at {1}{2}")]
+    UnknownFunction(String, Position, Suggestions),
+    #[error("Error: {0} expects {1} arguments but got {2} at {3}")]
+    ArityMismatch(String, Arity, usize, Position),
 }
 
 #[cfg(test)]
 mod traverser_tests {
+    use crate::span::Span;
     use std::rc::Rc;
-    use crate::ast::{ASTNode, ASTNodeType, CallExpression, NumberLiteral, Root, StringLiteral, Program};
+    use crate::ast::{ASTNode, ASTNodeType, CallExpression, NumberLiteral, StringLiteral, Program};
 
     use crate::traverser::{TransformError, travers_node, Visitors};
     use crate::visitor::NumberLiteralVisitFn;
-    use crate::visitor::{MockVisitor};
+    use crate::visitor::{Ancestor, MockVisitor};
 
     #[test]
     fn test_travers_number_literal_node() {
@@ -106,11 +149,11 @@ mod traverser_tests {
             .return_const(());
         visitor.insert(ASTNodeType::NumberLiteral, Box::new(mock));
 
-        let root = Rc::new(ASTNode::Root(Root { node_type: ASTNodeType::Root }));
-        let ast_node = NumberLiteral { node_type: ASTNodeType::NumberLiteral, value: "test".to_string() };
+        let mut path: Vec<Ancestor> = vec![];
+        let ast_node = NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "test".to_string() };
         let ast_node_rc = Rc::new(ASTNode::NumberLiteral(ast_node));
 
-        let traverse_node_resul = travers_node(&visitor, ast_node_rc, root);
+        let traverse_node_resul = travers_node(&visitor, ast_node_rc, &mut path);
         let result_node = traverse_node_resul.unwrap();
 
         assert_eq!(result_node, ());
@@ -129,11 +172,11 @@ mod traverser_tests {
             .return_const(());
         visitor.insert(ASTNodeType::StringLiteral, Box::new(mock));
 
-        let root = Rc::new(ASTNode::Root(Root { node_type: ASTNodeType::Root }));
-        let ast_node = StringLiteral { node_type: ASTNodeType::StringLiteral, value: "test".to_string() };
+        let mut path: Vec<Ancestor> = vec![];
+        let ast_node = StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "test".to_string() };
         let ast_node_rc = Rc::new(ASTNode::StringLiteral(ast_node));
 
-        let traverse_node_resul = travers_node(&visitor, ast_node_rc, root);
+        let traverse_node_resul = travers_node(&visitor, ast_node_rc, &mut path);
         let result_node = traverse_node_resul.unwrap();
 
         assert_eq!(result_node, ());
@@ -157,7 +200,7 @@ mod traverser_tests {
         let ctx = mock_array_traverser::traverse_array_context();
         ctx.expect()
             .times(1)
-            .withf(|visitors: &Visitors, array: &Vec<Rc<ASTNode>>, parent: &Rc<ASTNode>| {
+            .withf(|visitors: &Visitors, array: &Vec<Rc<ASTNode>>, parent: &Rc<ASTNode>, _path: &Vec<Ancestor>| {
                 {
                     let parent_node_type = parent.get_node_type();
                     if parent_node_type != ASTNodeType::CallExpression {
@@ -210,19 +253,21 @@ mod traverser_tests {
 
                 true
             })
-            .returning(|_, _, _| Ok(()));
+            .returning(|_, _, _, _| Ok(()));
 
-        let root = Rc::new(ASTNode::Root(Root { node_type: ASTNodeType::Root }));
+        let mut path: Vec<Ancestor> = vec![];
 
-        let param_ast_node1 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, value: "number_literal1".to_string() };
+        let param_ast_node1 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "number_literal1".to_string() };
         let param_ast_node_rc1 = Rc::new(ASTNode::NumberLiteral(param_ast_node1));
 
-        let param_ast_node2 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, value: "number_literal2".to_string() };
+        let param_ast_node2 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "number_literal2".to_string() };
         let param_ast_node_rc2 = Rc::new(ASTNode::NumberLiteral(param_ast_node2));
 
         let ast_node = CallExpression {
             node_type: ASTNodeType::CallExpression,
+            span: Span::default(),
             value: "call_expression_test_value".to_string(),
+            callee_span: Span::default(),
             params: vec![
                 param_ast_node_rc1,
                 param_ast_node_rc2,
@@ -230,7 +275,7 @@ mod traverser_tests {
         };
         let ast_node_rc = Rc::new(ASTNode::CallExpression(ast_node));
 
-        let traverse_node_resul = travers_node(&visitor, ast_node_rc, root);
+        let traverse_node_resul = travers_node(&visitor, ast_node_rc, &mut path);
         let result_node = traverse_node_resul.unwrap();
 
         assert_eq!(result_node, ());
@@ -254,7 +299,7 @@ mod traverser_tests {
         let ctx = mock_array_traverser::traverse_array_context();
         ctx.expect()
             .times(1)
-            .withf(|visitors: &Visitors, array: &Vec<Rc<ASTNode>>, parent: &Rc<ASTNode>| {
+            .withf(|visitors: &Visitors, array: &Vec<Rc<ASTNode>>, parent: &Rc<ASTNode>, _path: &Vec<Ancestor>| {
                 {
                     let parent_node_type = parent.get_node_type();
                     if parent_node_type != ASTNodeType::Program {
@@ -301,18 +346,19 @@ mod traverser_tests {
 
                 true
             })
-            .returning(|_, _, _| Ok(()));
+            .returning(|_, _, _, _| Ok(()));
 
-        let root = Rc::new(ASTNode::Root(Root { node_type: ASTNodeType::Root }));
+        let mut path: Vec<Ancestor> = vec![];
 
-        let param_ast_node1 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, value: "number_literal1".to_string() };
+        let param_ast_node1 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "number_literal1".to_string() };
         let param_ast_node_rc1 = Rc::new(ASTNode::NumberLiteral(param_ast_node1));
 
-        let param_ast_node2 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, value: "number_literal2".to_string() };
+        let param_ast_node2 = NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "number_literal2".to_string() };
         let param_ast_node_rc2 = Rc::new(ASTNode::NumberLiteral(param_ast_node2));
 
         let ast_node = Program {
             node_type: ASTNodeType::Program,
+            span: Span::default(),
             body: vec![
                 param_ast_node_rc1,
                 param_ast_node_rc2,
@@ -320,10 +366,193 @@ mod traverser_tests {
         };
         let ast_node_rc = Rc::new(ASTNode::Program(ast_node));
 
-        let traverse_node_resul = travers_node(&visitor, ast_node_rc, root);
+        let traverse_node_resul = travers_node(&visitor, ast_node_rc, &mut path);
         let result_node = traverse_node_resul.unwrap();
 
         assert_eq!(result_node, ());
     }
-}
 
+    #[test]
+    fn test_travers_root_node_has_no_parent() {
+        let mut visitor: Visitors = Visitors::new();
+
+        let mut mock: MockVisitor = MockVisitor::new();
+        mock.expect_enter()
+            .times(1)
+            .withf(|_, parent, path| parent.is_none() && path.is_empty())
+            .return_const(());
+        mock.expect_exit()
+            .times(1)
+            .withf(|_, parent, path| parent.is_none() && path.is_empty())
+            .return_const(());
+        visitor.insert(ASTNodeType::StringLiteral, Box::new(mock));
+
+        let mut path: Vec<Ancestor> = vec![];
+        let ast_node = StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "test".to_string() };
+        let ast_node_rc = Rc::new(ASTNode::StringLiteral(ast_node));
+
+        let traverse_node_result = travers_node(&visitor, ast_node_rc, &mut path);
+
+        assert!(traverse_node_result.is_ok());
+        assert!(path.is_empty());
+    }
+
+    #[test]
+    fn test_traverse_array_passes_ancestor_path() {
+        use std::cell::RefCell;
+        use crate::traverser::array_traverser;
+        use crate::visitor::Visitor;
+
+        /// Records the value of every number literal passed as an argument of `subtract`,
+        /// together with its position among the arguments.
+        struct InsideSubtract {
+            found: Rc<RefCell<Vec<(String, usize)>>>,
+        }
+
+        impl Visitor for InsideSubtract {
+            fn enter(&self, node: &ASTNode, parent: Option<Rc<ASTNode>>, path: &[Ancestor]) {
+                let is_subtract_argument = matches!(
+                    parent.as_deref(),
+                    Some(ASTNode::CallExpression(call_expression)) if call_expression.value == "subtract"
+                );
+
+                if let (ASTNode::NumberLiteral(number_literal), true) = (node, is_subtract_argument) {
+                    let index = path.last().unwrap().index;
+                    self.found.borrow_mut().push((number_literal.value.clone(), index));
+                }
+            }
+
+            fn exit(&self, _node: &ASTNode, _parent: Option<Rc<ASTNode>>, _path: &[Ancestor]) {}
+        }
+
+        let found = Rc::new(RefCell::new(vec![]));
+        let mut visitor: Visitors = Visitors::new();
+        visitor.insert(ASTNodeType::NumberLiteral, Box::new(InsideSubtract { found: found.clone() }));
+
+        let params = vec![
+            Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "4".to_string() })),
+            Rc::new(ASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "2".to_string() })),
+        ];
+        let subtract = Rc::new(ASTNode::CallExpression(CallExpression {
+            node_type: ASTNodeType::CallExpression,
+            span: Span::default(),
+            value: "subtract".to_string(),
+            callee_span: Span::default(),
+            params: params.clone(),
+        }));
+        let add = Rc::new(ASTNode::CallExpression(CallExpression {
+            node_type: ASTNodeType::CallExpression,
+            span: Span::default(),
+            value: "add".to_string(),
+            callee_span: Span::default(),
+            params: vec![subtract.clone()],
+        }));
+
+        let mut path: Vec<Ancestor> = vec![Ancestor { node: add, index: 0 }];
+        let traverse_result = array_traverser::traverse_array(&visitor, params, subtract, &mut path);
+
+        assert!(traverse_result.is_ok());
+        assert_eq!(path.len(), 1);
+        assert_eq!(*found.borrow(), vec![("4".to_string(), 0), ("2".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_travers_new_number_literal_node() {
+        use crate::ast::NewASTNode;
+        use crate::traverser::{travers_new_node, NewVisitors};
+        use crate::visitor::MockNewVisitor;
+
+        let mut visitor: NewVisitors = NewVisitors::new();
+
+        let mut mock: MockNewVisitor = MockNewVisitor::new();
+        mock.expect_enter()
+            .times(1)
+            .withf(|_, parent| parent.is_none())
+            .return_const(());
+        mock.expect_exit()
+            .times(1)
+            .withf(|_, parent| parent.is_none())
+            .return_const(());
+        visitor.insert(ASTNodeType::NumberLiteral, Box::new(mock));
+
+        let ast_node = NewASTNode::NumberLiteral(NumberLiteral { node_type: ASTNodeType::NumberLiteral, span: Span::default(), value: "1".to_string() });
+
+        let traverse_node_result = travers_new_node(&visitor, &ast_node, None);
+
+        assert!(traverse_node_result.is_ok());
+    }
+
+    #[test]
+    fn test_travers_new_program_node() {
+        use crate::ast::{CallExpressionWithCallee, ExpressionStatement, Identifier, NewASTNode, NewProgram};
+        use crate::traverser::{new_traverser, NewVisitors};
+        use crate::visitor::MockNewVisitor;
+
+        let mut visitor: NewVisitors = NewVisitors::new();
+
+        let mut statement_mock: MockNewVisitor = MockNewVisitor::new();
+        statement_mock.expect_enter()
+            .times(1)
+            .withf(|_, parent| parent.map(|p| p.get_node_type()) == Some(ASTNodeType::Program))
+            .return_const(());
+        statement_mock.expect_exit()
+            .times(1)
+            .return_const(());
+        visitor.insert(ASTNodeType::ExpressionStatement, Box::new(statement_mock));
+
+        let mut call_mock: MockNewVisitor = MockNewVisitor::new();
+        call_mock.expect_enter()
+            .times(1)
+            .withf(|_, parent| parent.map(|p| p.get_node_type()) == Some(ASTNodeType::ExpressionStatement))
+            .return_const(());
+        call_mock.expect_exit()
+            .times(1)
+            .return_const(());
+        visitor.insert(ASTNodeType::CallExpression, Box::new(call_mock));
+
+        let mut string_mock: MockNewVisitor = MockNewVisitor::new();
+        string_mock.expect_enter()
+            .times(2)
+            .withf(|_, parent| parent.map(|p| p.get_node_type()) == Some(ASTNodeType::CallExpression))
+            .return_const(());
+        string_mock.expect_exit()
+            .times(2)
+            .return_const(());
+        visitor.insert(ASTNodeType::StringLiteral, Box::new(string_mock));
+
+        let mut identifier_mock: MockNewVisitor = MockNewVisitor::new();
+        identifier_mock.expect_enter()
+            .times(1)
+            .withf(|node, parent| matches!(node, NewASTNode::Identifier(identifier) if identifier.name == "fullName")
+                && parent.map(|p| p.get_node_type()) == Some(ASTNodeType::CallExpression))
+            .return_const(());
+        identifier_mock.expect_exit()
+            .times(1)
+            .return_const(());
+        visitor.insert(ASTNodeType::Identifier, Box::new(identifier_mock));
+
+        let new_program = NewASTNode::NewProgram(NewProgram {
+            node_type: ASTNodeType::Program,
+            span: Span::default(),
+            body: vec![
+                NewASTNode::ExpressionStatement(ExpressionStatement {
+                    node_type: ASTNodeType::ExpressionStatement,
+                    span: Span::default(),
+                    expression: Box::new(NewASTNode::CallExpressionWithCallee(CallExpressionWithCallee {
+                        node_type: ASTNodeType::CallExpression,
+                        span: Span::default(),
+                        callee: Identifier { node_type: ASTNodeType::Identifier, span: Span::default(), name: "fullName".to_string() },
+                        arguments: vec![
+                            NewASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "hoge".to_string() }),
+                            NewASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "foo".to_string() }),
+                        ],
+                    })),
+                }),
+            ],
+        });
+
+        let traverse_result = new_traverser(&new_program, &visitor);
+
+        assert!(traverse_result.is_ok());
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+use crate::ast::{ASTNode, CallExpression, Program};
+use crate::eval::IF;
+use crate::functions::{Arity, FunctionRegistry};
+use crate::span::Position;
+
+/// Static types of values. `Var` is a type not inferred yet, solved by unification.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Type {
+    Number,
+    String,
+    Bool,
+    Nil,
+    List(Box<Type>),
+    /// `rest` is the type of each argument after `params`, for variadic functions.
+    Function { params: Vec<Type>, rest: Option<Box<Type>>, result: Box<Type> },
+    Var(usize),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "Number"),
+            Type::String => write!(f, "String"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Nil => write!(f, "Nil"),
+            Type::List(element) => write!(f, "List<{}>", element),
+            Type::Function { params, rest, result } => {
+                let mut params = params.iter().map(|param| param.to_string()).collect::<Vec<String>>();
+                if let Some(rest) = rest {
+                    params.push(format!("...{}", rest));
+                }
+                write!(f, "({}) -> {}", params.join(", "), result)
+            }
+            Type::Var(index) => write!(f, "T{}", index),
+        }
+    }
+}
+
+/// Signature of a builtin or special form. `Var`s in it are generic and
+/// instantiated afresh for each call; each rest argument gets its own instance.
+pub fn signature(name: &str) -> Option<Type> {
+    let function = |params: Vec<Type>, rest: Option<Type>, result: Type| Type::Function {
+        params,
+        rest: rest.map(Box::new),
+        result: Box::new(result),
+    };
+
+    let signature = match name {
+        "add" | "subtract" | "multiply" | "divide" => function(vec![Type::Number, Type::Number], None, Type::Number),
+        "lt" | "le" | "gt" | "ge" => function(vec![Type::Number, Type::Number], None, Type::Bool),
+        "eq" => function(vec![Type::Var(0), Type::Var(1)], None, Type::Bool),
+        "concat" => function(vec![], Some(Type::String), Type::String),
+        "print" => function(vec![], Some(Type::Var(0)), Type::Nil),
+        IF => function(vec![Type::Bool, Type::Var(0), Type::Var(0)], None, Type::Var(0)),
+        _ => return None,
+    };
+
+    Some(signature)
+}
+
+/// Infers the type of each top-level expression, with the builtins' signatures.
+/// Calls to functions without a signature are not checked and have a `Var` type,
+/// as the JavaScript they compile to may call functions defined elsewhere.
+pub fn check(program: &Program) -> anyhow::Result<Vec<Type>, TypeError> {
+    check_with_functions(program, &FunctionRegistry::with_builtins())
+}
+
+/// Like `check`, with the signatures in `functions`.
+pub fn check_with_functions(program: &Program, functions: &FunctionRegistry) -> anyhow::Result<Vec<Type>, TypeError> {
+    let mut checker = Checker { functions, substitution: vec![] };
+
+    let mut types = vec![];
+    for node in &program.body {
+        let inferred = checker.infer(node)?;
+        types.push(renumber(&checker.resolve(&inferred), &mut HashMap::new()));
+    }
+
+    Ok(types)
+}
+
+/// Numbers the `Var`s left in a resolved type from `T0`, in order of appearance.
+fn renumber(t: &Type, vars: &mut HashMap<usize, usize>) -> Type {
+    match t {
+        Type::Var(index) => {
+            let next = vars.len();
+            Type::Var(*vars.entry(*index).or_insert(next))
+        }
+        Type::List(element) => Type::List(Box::new(renumber(element, vars))),
+        Type::Function { params, rest, result } => Type::Function {
+            params: params.iter().map(|param| renumber(param, vars)).collect(),
+            rest: rest.as_ref().map(|rest| Box::new(renumber(rest, vars))),
+            result: Box::new(renumber(result, vars)),
+        },
+        t => t.clone(),
+    }
+}
+
+struct Checker<'a> {
+    functions: &'a FunctionRegistry,
+    /// What each `Var` was unified with, if anything.
+    substitution: Vec<Option<Type>>,
+}
+
+impl Checker<'_> {
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+
+        Type::Var(self.substitution.len() - 1)
+    }
+
+    /// Replaces solved `Var`s in `t`.
+    fn resolve(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(index) => match &self.substitution[*index] {
+                Some(solved) => self.resolve(solved),
+                None => t.clone(),
+            },
+            Type::List(element) => Type::List(Box::new(self.resolve(element))),
+            Type::Function { params, rest, result } => Type::Function {
+                params: params.iter().map(|param| self.resolve(param)).collect(),
+                rest: rest.as_ref().map(|rest| Box::new(self.resolve(rest))),
+                result: Box::new(self.resolve(result)),
+            },
+            t => t.clone(),
+        }
+    }
+
+    /// Replaces the generic `Var`s of a signature with fresh ones, the same for equal `Var`s.
+    fn instantiate(&mut self, t: &Type, vars: &mut HashMap<usize, Type>) -> Type {
+        match t {
+            Type::Var(index) => match vars.get(index) {
+                Some(var) => var.clone(),
+                None => {
+                    let var = self.fresh();
+                    vars.insert(*index, var.clone());
+                    var
+                }
+            },
+            Type::List(element) => Type::List(Box::new(self.instantiate(element, vars))),
+            Type::Function { params, rest, result } => Type::Function {
+                params: params.iter().map(|param| self.instantiate(param, vars)).collect(),
+                rest: rest.as_ref().map(|rest| Box::new(self.instantiate(rest, vars))),
+                result: Box::new(self.instantiate(result, vars)),
+            },
+            t => t.clone(),
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        match (self.resolve(a), self.resolve(b)) {
+            (Type::Var(a), Type::Var(b)) if a == b => true,
+            (Type::Var(index), t) | (t, Type::Var(index)) => {
+                self.substitution[index] = Some(t);
+                true
+            }
+            (Type::List(a), Type::List(b)) => self.unify(&a, &b),
+            (Type::Function { params: a_params, rest: a_rest, result: a_result }, Type::Function { params: b_params, rest: b_rest, result: b_result }) => {
+                a_params.len() == b_params.len()
+                    && a_params.iter().zip(&b_params).all(|(a, b)| self.unify(a, b))
+                    && match (a_rest, b_rest) {
+                        (Some(a), Some(b)) => self.unify(&a, &b),
+                        (None, None) => true,
+                        _ => false,
+                    }
+                    && self.unify(&a_result, &b_result)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    fn infer(&mut self, node: &ASTNode) -> Result<Type, TypeError> {
+        match node {
+            ASTNode::NumberLiteral(_) => Ok(Type::Number),
+            ASTNode::StringLiteral(_) => Ok(Type::String),
+            ASTNode::CallExpression(call_expression) => self.infer_call(call_expression),
+            ASTNode::Identifier(identifier) => Err(TypeError::UnboundSymbol(identifier.name.clone(), identifier.span.start)),
+            ASTNode::Program(program) => Err(TypeError::NestedProgram(program.span.start)),
+            ASTNode::Root(_) => Ok(self.fresh()),
+        }
+    }
+
+    fn infer_call(&mut self, call_expression: &CallExpression) -> Result<Type, TypeError> {
+        let name = &call_expression.value;
+        let Some(signature) = self.functions.get(name).and_then(|function| function.signature.clone()) else {
+            for param in &call_expression.params {
+                self.infer(param)?;
+            }
+            return Ok(self.fresh());
+        };
+
+        let Type::Function { params, rest, result } = self.instantiate(&signature, &mut HashMap::new()) else {
+            unreachable!("signatures are function types");
+        };
+        let argc = call_expression.params.len();
+        let arity = Arity { min: params.len(), max: rest.is_none().then_some(params.len()) };
+        if !arity.accepts(argc) {
+            return Err(TypeError::ArityMismatch(name.clone(), arity, argc, call_expression.callee_span.start));
+        }
+
+        for (index, argument) in call_expression.params.iter().enumerate() {
+            let expected = match params.get(index) {
+                Some(param) => param.clone(),
+                None => self.instantiate(rest.as_deref().unwrap_or(&Type::Nil), &mut HashMap::new()),
+            };
+            let found = self.infer(argument)?;
+            if !self.unify(&expected, &found) {
+                return Err(TypeError::Mismatch(
+                    name.clone(),
+                    index + 1,
+                    Box::new(self.resolve(&expected)),
+                    Box::new(self.resolve(&found)),
+                    argument.get_span().start,
+                ));
+            }
+        }
+
+        Ok(self.resolve(&result))
+    }
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum TypeError {
+    #[error("Error: Type mismatch in argument {1} of {0}: expected {2} but found {3} at {4}")]
+    Mismatch(String, usize, Box<Type>, Box<Type>, Position),
+    #[error("Error: {0} expects {1} arguments but got {2} at {3}")]
+    ArityMismatch(String, Arity, usize, Position),
+    #[error("Error: Unbound symbol: {0} at {1}")]
+    UnboundSymbol(String, Position),
+    #[error("Error: Cannot type check a nested program at {0}")]
+    NestedProgram(Position),
+}
+
+#[cfg(test)]
+mod types_tests {
+    use crate::parser::parser;
+    use crate::tokenizer::tokenizer;
+    use crate::types::{check, signature, Type, TypeError};
+    use crate::span::Position;
+
+    fn check_code(code: &str) -> Result<Vec<Type>, TypeError> {
+        check(&parser(tokenizer(code.to_string()).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_check() {
+        let types = check_code("(add 1 (multiply 2 3)) (concat 'a' 'b' 'c') (if (lt 1 2) 'yes' 'no') (print 1 'a') (eq 1 'a') (fullName 'hoge')").unwrap();
+
+        assert_eq!(types, vec![Type::Number, Type::String, Type::String, Type::Nil, Type::Bool, Type::Var(0)]);
+    }
+
+    #[test]
+    fn test_check_points_at_literal() {
+        let result_error = check_code("(print\n  (add 1 'two'))").unwrap_err();
+
+        assert_eq!(result_error, TypeError::Mismatch("add".to_string(), 2, Box::new(Type::Number), Box::new(Type::String), Position { offset: 16, line: 2, column: 9 }));
+        assert_eq!(result_error.to_string(), "Error: Type mismatch in argument 2 of add: expected Number but found String at 2:10");
+    }
+
+    #[test]
+    fn test_check_if_branches() {
+        let result_error = check_code("(if (gt 2 1) 1 'one')").unwrap_err();
+
+        assert_eq!(result_error.to_string(), "Error: Type mismatch in argument 3 of if: expected Number but found String at 1:16");
+    }
+
+    #[test]
+    fn test_check_arity() {
+        let result_error = check_code("(concat 'a' (subtract 1))").unwrap_err();
+
+        assert_eq!(result_error.to_string(), "Error: subtract expects 2 arguments but got 1 at 1:14");
+    }
+
+    #[test]
+    fn test_signature_display() {
+        assert_eq!(signature("add").unwrap().to_string(), "(Number, Number) -> Number");
+        assert_eq!(signature("print").unwrap().to_string(), "(...T0) -> Nil");
+        assert_eq!(Type::List(Box::new(Type::Bool)).to_string(), "List<Bool>");
+    }
+}
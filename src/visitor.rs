@@ -1,5 +1,6 @@
-use crate::ast::{ASTNode, ASTNodeType, NumberLiteral, StringLiteral};
-use std::collections::HashMap;
+use crate::ast::{ASTNode, NewASTNode};
+use crate::transformer::TransformContext;
+use std::cell::RefCell;
 use std::rc::Rc;
 
 #[cfg(test)]
@@ -7,35 +8,62 @@ use mockall::automock;
 
 #[cfg_attr(test, automock)]
 pub trait Visitor {
-    fn enter(&self, node: &ASTNode, parent: Rc<ASTNode>) { println!("This is default implementation of enter.") }
-    fn exit(&self, node: &ASTNode, parent: Rc<ASTNode>) { println!("This is default implementation of exit.") }
+    fn enter<'a>(&self, _node: &'a ASTNode, _parent: Option<Rc<ASTNode>>, _path: &'a [Ancestor]) {}
+    fn exit<'a>(&self, _node: &'a ASTNode, _parent: Option<Rc<ASTNode>>, _path: &'a [Ancestor]) {}
 }
 
-pub struct ProgramVisitFn;
-pub struct NumberLiteralVisitFn;
-pub struct StringLiteralVisitFn;
-pub struct CallExpressionVisitFn;
+/// An ancestor of the visited node, and the index within its `body` or `params`
+/// of the child that leads down to the visited node.
+#[derive(Debug, Clone)]
+pub struct Ancestor {
+    pub node: Rc<ASTNode>,
+    pub index: usize,
+}
 
-impl Visitor for ProgramVisitFn {
-    fn enter(&self, node: &ASTNode, parent: Rc<ASTNode>) {
-        println!("enter program")
-    }
+/// Visitor for the transformed `NewASTNode` tree.
+/// The parent is `None` for the node the traversal starts from.
+#[cfg_attr(test, automock)]
+pub trait NewVisitor {
+    fn enter<'a>(&self, _node: &'a NewASTNode, _parent: Option<&'a NewASTNode>) {}
+    fn exit<'a>(&self, _node: &'a NewASTNode, _parent: Option<&'a NewASTNode>) {}
+}
+
+/// Visitors used by `transformer`. They share a `TransformContext`
+/// and build the new AST into it while the source AST is traversed.
+pub struct NumberLiteralVisitFn {
+    pub(crate) context: Rc<RefCell<TransformContext>>,
+}
+pub struct StringLiteralVisitFn {
+    pub(crate) context: Rc<RefCell<TransformContext>>,
+}
+pub struct CallExpressionVisitFn {
+    pub(crate) context: Rc<RefCell<TransformContext>>,
 }
 
 impl Visitor for NumberLiteralVisitFn {
-    fn enter(&self, node: &ASTNode, parent: Rc<ASTNode>) {
-        println!("enter number")
+    fn enter(&self, node: &ASTNode, _parent: Option<Rc<ASTNode>>, _path: &[Ancestor]) {
+        if let ASTNode::NumberLiteral(number_literal) = node {
+            self.context.borrow_mut().push(NewASTNode::NumberLiteral(number_literal.clone()));
+        }
     }
 }
 
 impl Visitor for StringLiteralVisitFn {
-    fn enter(&self, node: &ASTNode, parent: Rc<ASTNode>) {
-        println!("enter string")
+    fn enter(&self, node: &ASTNode, _parent: Option<Rc<ASTNode>>, _path: &[Ancestor]) {
+        if let ASTNode::StringLiteral(string_literal) = node {
+            self.context.borrow_mut().push(NewASTNode::StringLiteral(string_literal.clone()));
+        }
     }
 }
 
 impl Visitor for CallExpressionVisitFn {
-    fn enter(&self, node: &ASTNode, parent: Rc<ASTNode>) {
-        println!("enter call-expression")
+    fn enter(&self, node: &ASTNode, _parent: Option<Rc<ASTNode>>, _path: &[Ancestor]) {
+        if let ASTNode::CallExpression(call_expression) = node {
+            self.context.borrow_mut().open_call(call_expression);
+        }
+    }
+
+    fn exit(&self, _node: &ASTNode, _parent: Option<Rc<ASTNode>>, _path: &[Ancestor]) {
+        self.context.borrow_mut().close_call();
     }
 }
@@ -0,0 +1,141 @@
+use std::io::Write;
+use thiserror::Error;
+use crate::bytecode::{Chunk, Instruction};
+use crate::eval::{builtin_error, Environment, EvalError, Value, IF};
+
+/// Values on the VM stack beyond this fail with `VmError::StackOverflow`.
+pub const DEFAULT_MAX_STACK: usize = 1_024;
+/// Instructions executed beyond this fail with `VmError::BudgetExhausted`.
+pub const DEFAULT_MAX_INSTRUCTIONS: usize = 1_000_000;
+
+/// Runs `chunk` and returns the values of its top-level expressions, like `eval`.
+pub fn run(chunk: &Chunk, environment: &Environment, out: &mut dyn Write) -> anyhow::Result<Vec<Value>, VmError> {
+    run_with_limits(chunk, environment, out, DEFAULT_MAX_STACK, DEFAULT_MAX_INSTRUCTIONS)
+}
+
+pub fn run_with_limits(chunk: &Chunk, environment: &Environment, out: &mut dyn Write, max_stack: usize, max_instructions: usize) -> anyhow::Result<Vec<Value>, VmError> {
+    let mut stack: Vec<Value> = vec![];
+    let mut results: Vec<Value> = vec![];
+    let mut pc: usize = 0;
+    let mut executed: usize = 0;
+
+    while let Some(instruction) = chunk.code.get(pc) {
+        executed += 1;
+        if executed > max_instructions {
+            return Err(VmError::BudgetExhausted(max_instructions));
+        }
+        pc += 1;
+
+        match *instruction {
+            Instruction::PushConst(index) => push(&mut stack, chunk.constants[index].clone(), max_stack)?,
+            Instruction::Call { site, argc } => {
+                let call_site = &chunk.calls[site];
+                let args = stack.split_off(stack.len() - argc);
+                let builtin = environment.get(&call_site.name)
+                    .ok_or_else(|| environment.unknown_function(&call_site.name, call_site.callee_span))?;
+                let value = builtin(&args, out)
+                    .map_err(|error| builtin_error(error, &call_site.name, chunk.spans[pc - 1], &call_site.arg_spans, &args))?;
+                push(&mut stack, value, max_stack)?;
+            }
+            Instruction::Jump(target) => pc = target,
+            Instruction::JumpIfFalse(target) => match pop(&mut stack) {
+                Value::Bool(true) => {}
+                Value::Bool(false) => pc = target,
+                value => return Err(EvalError::TypeMismatch(IF.to_string(), "bool", value.type_name(), chunk.spans[pc - 1].start).into()),
+            },
+            Instruction::Pop => {
+                pop(&mut stack);
+            }
+            Instruction::Yield => results.push(pop(&mut stack)),
+            Instruction::Ret => break,
+            Instruction::Fail(index) => return Err(chunk.errors[index].clone().into()),
+        }
+    }
+
+    Ok(results)
+}
+
+fn push(stack: &mut Vec<Value>, value: Value, max_stack: usize) -> Result<(), VmError> {
+    if stack.len() >= max_stack {
+        return Err(VmError::StackOverflow(max_stack));
+    }
+    stack.push(value);
+
+    Ok(())
+}
+
+/// The compiler never pops more than it pushed.
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("bytecode popped an empty stack")
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum VmError {
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+    #[error("Error: Stack overflow: more than {0} values")]
+    StackOverflow(usize),
+    #[error("Error: Instruction budget exhausted: more than {0} instructions")]
+    BudgetExhausted(usize),
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use crate::bytecode::compile;
+    use crate::eval::{eval, Environment};
+    use crate::parser::parser;
+    use crate::tokenizer::tokenizer;
+    use crate::vm::{run, run_with_limits, VmError};
+
+    /// Runs `code` with both the evaluator and the VM and checks they agree on values, output and errors.
+    fn assert_same_as_eval(code: &str) {
+        let program = parser(tokenizer(code.to_string()).unwrap()).unwrap();
+        let environment = Environment::new();
+
+        let mut eval_out: Vec<u8> = vec![];
+        let eval_result = eval(&program, &environment, &mut eval_out).map_err(|error| error.to_string());
+        let mut vm_out: Vec<u8> = vec![];
+        let vm_result = run(&compile(&program), &environment, &mut vm_out).map_err(|error| error.to_string());
+
+        assert_eq!(vm_result, eval_result, "{}", code);
+        assert_eq!(vm_out, eval_out, "{}", code);
+    }
+
+    #[test]
+    fn test_vm_matches_eval() {
+        let programs = [
+            "(add 2 (subtract 4 2)) (multiply 3 (divide 9 3))",
+            "(print (concat 'hoge' 'foo') 1) (print)",
+            "(if (lt 1 2) (print 'then') (print 'else')) (if (ge 1 2) 1 (if (eq 'a' 'a') 'b' 'c'))",
+            "(eq (gt 3 2) (le 2 2))",
+            "(print 1) (fullName 'hoge' (print 2))",
+            "(add 1\n  'hoge')",
+            "(print 'before') (divide 1 (subtract 2 2)) (print 'after')",
+            "(multiply 9223372036854775807 2)",
+            "(if 1 2 3)",
+            "(subtract 1)",
+            "(print 1) (add 99999999999999999999 1)",
+            "(print 1) (print x)",
+            "(print 1) (if 1 2)",
+        ];
+
+        for code in programs {
+            assert_same_as_eval(code);
+        }
+    }
+
+    #[test]
+    fn test_vm_limits() {
+        let program = parser(tokenizer("(add 1 (add 2 (add 3 4)))".to_string()).unwrap()).unwrap();
+        let chunk = compile(&program);
+        let environment = Environment::new();
+
+        let stack_error = run_with_limits(&chunk, &environment, &mut vec![], 3, 100).unwrap_err();
+        let budget_error = run_with_limits(&chunk, &environment, &mut vec![], 4, 5).unwrap_err();
+
+        assert_eq!(stack_error, VmError::StackOverflow(3));
+        assert_eq!(stack_error.to_string(), "Error: Stack overflow: more than 3 values");
+        assert_eq!(budget_error, VmError::BudgetExhausted(5));
+        assert!(run_with_limits(&chunk, &environment, &mut vec![], 4, 100).is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+use thiserror::Error;
+use crate::ast::NewASTNode;
+use crate::span::Position;
+
+/// Builtins the WAT backend can lower, with the instruction each one maps to.
+const INSTRUCTIONS: [(&str, &str); 4] = [
+    ("add", "i64.add"),
+    ("subtract", "i64.sub"),
+    ("multiply", "i64.mul"),
+    ("divide", "i64.div_s"),
+];
+
+/// Lowers a program of numeric call trees to a WAT module exporting `main`.
+/// `main` returns the value of the last statement and drops the others.
+pub fn generate_wat(new_node: &NewASTNode) -> anyhow::Result<String, WatError> {
+    let statements = match new_node {
+        NewASTNode::NewProgram(new_program) => new_program.body.iter().collect::<Vec<&NewASTNode>>(),
+        node => vec![node],
+    };
+
+    let mut lines: Vec<String> = vec![
+        "(module".to_string(),
+        "  (func $main (export \"main\") (result i64)".to_string(),
+    ];
+    for (index, statement) in statements.iter().enumerate() {
+        let expression = generate_expression(statement)?;
+        if index + 1 < statements.len() {
+            lines.push(format!("    (drop {})", expression));
+        } else {
+            lines.push(format!("    {}", expression));
+        }
+    }
+    if statements.is_empty() {
+        lines.push("    (i64.const 0)".to_string());
+    }
+    lines.push("  )".to_string());
+    lines.push(")".to_string());
+
+    Ok(lines.join("\n"))
+}
+
+enum Step<'a> {
+    Enter(&'a NewASTNode),
+    Exit(&'a str, usize),
+}
+
+/// Generates one folded instruction without recursion, like `code_generator`.
+fn generate_expression(new_node: &NewASTNode) -> Result<String, WatError> {
+    let mut steps: Vec<Step> = vec![Step::Enter(new_node)];
+    let mut codes: Vec<String> = vec![];
+
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Enter(node) => match node {
+                NewASTNode::ExpressionStatement(expression_statement) => steps.push(Step::Enter(&expression_statement.expression)),
+                NewASTNode::CallExpressionWithCallee(call_expression) => {
+                    let name = call_expression.callee.name.as_str();
+                    let (_, instruction) = INSTRUCTIONS.iter()
+                        .find(|(builtin, _)| *builtin == name)
+                        .ok_or_else(|| WatError::UnsupportedFunction(name.to_string(), call_expression.callee.span.start))?;
+                    if call_expression.arguments.len() != 2 {
+                        return Err(WatError::ArityMismatch(name.to_string(), call_expression.arguments.len(), call_expression.span.start));
+                    }
+
+                    steps.push(Step::Exit(instruction, 2));
+                    steps.extend(call_expression.arguments.iter().rev().map(Step::Enter));
+                }
+                NewASTNode::NumberLiteral(number_literal) => {
+                    let value: i64 = number_literal.value.parse()
+                        .map_err(|_| WatError::InvalidNumber(number_literal.value.clone(), number_literal.span.start))?;
+                    codes.push(format!("(i64.const {})", value));
+                }
+                NewASTNode::StringLiteral(string_literal) => return Err(WatError::UnsupportedString(string_literal.span.start)),
+                NewASTNode::Identifier(identifier) => return Err(WatError::UnsupportedFunction(identifier.name.clone(), identifier.span.start)),
+                NewASTNode::NewProgram(new_program) => return Err(WatError::NestedProgram(new_program.span.start)),
+            },
+            Step::Exit(instruction, arity) => {
+                let operands = codes.split_off(codes.len() - arity);
+                codes.push(format!("({} {})", instruction, operands.join(" ")));
+            }
+        }
+    }
+
+    Ok(codes.pop().unwrap_or_default())
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum WatError {
+    #[error("Error: Cannot compile {0} to WAT (expected add, subtract, multiply or divide) at {1}")]
+    UnsupportedFunction(String, Position),
+    #[error("Error: {0} takes 2 arguments in WAT but got {1} at {2}")]
+    ArityMismatch(String, usize, Position),
+    #[error("Error: Cannot compile strings to WAT at {0}")]
+    UnsupportedString(Position),
+    #[error("Error: Number does not fit in i64: {0} at {1}")]
+    InvalidNumber(String, Position),
+    #[error("Error: Cannot compile a nested program to WAT at {0}")]
+    NestedProgram(Position),
+}
+
+#[cfg(test)]
+mod wat_tests {
+    use crate::ast::{ASTNodeType, NewASTNode, StringLiteral};
+    use crate::span::Span;
+    use crate::test_support::new_ast::{call, number, program, statement};
+    use crate::wat::generate_wat;
+
+    #[test]
+    fn test_generate_wat() {
+        let new_program = program(vec![
+            statement(call("multiply", vec![number("3"), number("4")])),
+            statement(call("add", vec![number("2"), call("subtract", vec![number("4"), number("2")])])),
+        ]);
+
+        assert_eq!(generate_wat(&new_program).unwrap(), [
+            "(module",
+            "  (func $main (export \"main\") (result i64)",
+            "    (drop (i64.mul (i64.const 3) (i64.const 4)))",
+            "    (i64.add (i64.const 2) (i64.sub (i64.const 4) (i64.const 2)))",
+            "  )",
+            ")",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn test_generate_wat_rejects_non_numeric_code() {
+        let string = NewASTNode::StringLiteral(StringLiteral { node_type: ASTNodeType::StringLiteral, span: Span::default(), value: "hoge".to_string() });
+        let cases = [
+            (call("fullName", vec![number("1"), number("2")]), "Error: Cannot compile fullName to WAT (expected add, subtract, multiply or divide) at 0:1"),
+            (call("add", vec![number("1")]), "Error: add takes 2 arguments in WAT but got 1 at 0:1"),
+            (call("add", vec![number("1"), string]), "Error: Cannot compile strings to WAT at 0:1"),
+            (number("9223372036854775808"), "Error: Number does not fit in i64: 9223372036854775808 at 0:1"),
+        ];
+
+        for (expression, message) in cases {
+            let result_error = generate_wat(&program(vec![statement(expression)])).unwrap_err();
+
+            assert_eq!(result_error.to_string(), message);
+        }
+    }
+}
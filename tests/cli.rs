@@ -0,0 +1,472 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs the compiler on `input`, whether it succeeds or not.
+fn run_compiler(args: &[&str], input: &str) -> std::process::Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_the-super-tiny-compiler-rust"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+fn compile(args: &[&str], input: &str) -> String {
+    let output = run_compiler(args, input);
+    assert!(output.status.success(), "compiler exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr));
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Runs the compiler on `input`, expecting it to fail, and returns what it wrote to stderr.
+fn compile_error(args: &[&str], input: &str) -> String {
+    let output = run_compiler(args, input);
+    assert!(!output.status.success(), "compiler succeeded on {}", input);
+
+    String::from_utf8(output.stderr).unwrap()
+}
+
+const CODE: &str = "
+    (add 2 (subtract 4 2))
+    (fullName 'hoge' 'foo')
+";
+
+#[test]
+fn test_compile_to_js() {
+    let output = compile(&[], CODE);
+
+    assert_eq!(output, "add('2',subtract('4','2'))\nfullName('hoge','foo')\n");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_emit_each_stage_and_read_it_back() {
+    let tokens = compile(&["--emit", "tokens"], CODE);
+    let ast = compile(&["--emit", "ast"], CODE);
+    let new_ast = compile(&["--emit", "new-ast"], CODE);
+
+    assert_eq!(compile(&["--from", "tokens", "--emit", "ast"], &tokens), ast);
+    assert_eq!(compile(&["--from", "ast", "--emit", "new-ast"], &ast), new_ast);
+    assert_eq!(compile(&["--from", "new-ast"], &new_ast), compile(&[], CODE));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_read_back_deeply_nested_stages() {
+    // Each call is two levels of JSON, so this is far past serde_json's default limit of 128.
+    let code = format!("{}1{}", "(print ".repeat(300), ")".repeat(300));
+    let ast = compile(&["--emit", "ast"], &code);
+    let new_ast = compile(&["--emit", "new-ast"], &code);
+
+    assert_eq!(compile(&["--from", "ast", "--emit", "new-ast"], &ast), new_ast);
+    assert_eq!(compile(&["--from", "new-ast"], &new_ast), compile(&[], &code));
+}
+
+#[cfg(feature = "serde")]
+fn nested_ast(depth: usize) -> String {
+    format!(
+        r#"{{ "type": "Program", "body": [{}{{ "type": "NumberLiteral", "value": "1" }}{}] }}"#,
+        r#"{ "type": "CallExpression", "value": "print", "params": ["#.repeat(depth),
+        "] }".repeat(depth),
+    )
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_reject_ast_deeper_than_the_parser_allows() {
+    assert!(compile(&["--from", "ast"], &nested_ast(1_000)).starts_with("print(print("));
+
+    for depth in [1_001, 20_000, 1_000_000] {
+        let stderr = compile_error(&["--from", "ast"], &nested_ast(depth));
+        assert!(stderr.contains("Error: Nesting too deep: more than 1000 levels of call expressions"), "{}", stderr);
+    }
+}
+
+#[cfg(feature = "serde")]
+fn nested_new_ast(depth: usize) -> String {
+    format!(
+        r#"{{ "type": "Program", "body": [{{ "type": "ExpressionStatement", "expression": {}{{ "type": "NumberLiteral", "value": "1" }}{} }}] }}"#,
+        r#"{ "type": "CallExpression", "callee": { "name": "print" }, "arguments": ["#.repeat(depth),
+        "] }".repeat(depth),
+    )
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_reject_new_ast_deeper_than_the_parser_allows() {
+    assert!(compile(&["--from", "new-ast"], &nested_new_ast(1_000)).starts_with("print(print("));
+
+    for depth in [1_001, 1_000_000] {
+        let stderr = compile_error(&["--from", "new-ast"], &nested_new_ast(depth));
+        assert!(stderr.contains("Error: Nesting too deep: more than 1000 levels of call expressions"), "{}", stderr);
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_emit_estree() {
+    let output = compile(&["--emit", "estree"], "(add 2\n  'hoge')");
+    let estree: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+    let call_expression = &estree["body"][0]["expression"];
+    assert_eq!(estree["type"], "Program");
+    assert_eq!(call_expression["type"], "CallExpression");
+    assert_eq!(call_expression["callee"]["name"], "add");
+    assert_eq!(call_expression["range"], serde_json::json!([0, 16]));
+    assert_eq!(call_expression["arguments"][1]["raw"], "'hoge'");
+    assert_eq!(call_expression["arguments"][1]["loc"]["start"], serde_json::json!({ "line": 2, "column": 2 }));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_emit_estree_of_folded_literals() {
+    let output = compile(&["-O1", "--emit", "estree"], "(print (add 2 (subtract 4 2)) (concat 'hoge' 'foo'))");
+    let estree: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+    let arguments = &estree["body"][0]["expression"]["arguments"];
+    assert_eq!(arguments[0]["value"], 4);
+    assert_eq!(arguments[0]["raw"], "4");
+    assert_eq!(arguments[1]["raw"], "\"hogefoo\"");
+    assert_eq!(arguments[0]["range"], serde_json::json!([7, 29]));
+}
+
+#[test]
+fn test_emit_dot() {
+    let dot_ast = compile(&["--emit", "dot-ast"], "(add 2 'hoge')");
+    let dot_new_ast = compile(&["--emit", "dot-new-ast"], "(add 2 'hoge')");
+
+    assert!(dot_ast.starts_with("digraph AST {\n"));
+    assert!(dot_ast.contains("  n1 [label=\"CallExpression\\nadd\"];\n"));
+    assert!(dot_ast.contains("  n1 -> n3 [label=\"params[1]\"];\n"));
+    assert!(dot_new_ast.starts_with("digraph NewAST {\n"));
+    assert!(dot_new_ast.contains("  n3 [label=\"Identifier\\nadd\"];\n"));
+    assert!(dot_new_ast.contains("  n4 [label=\"NumberLiteral\\n2\"];\n"));
+}
+
+#[test]
+fn test_emit_dot_highlight() {
+    let dot_ast = compile(&["--emit", "dot-ast", "--highlight", "StringLiteral"], "(add 2 'hoge')");
+    let dot_new_ast = compile(&["--emit", "dot-new-ast", "--highlight", "Identifier", "--highlight", "NumberLiteral"], "(add 2 'hoge')");
+
+    assert!(dot_ast.contains("  n1 [label=\"CallExpression\\nadd\"];\n"));
+    assert!(dot_ast.contains("  n3 [label=\"StringLiteral\\nhoge\", style=filled, fillcolor=lightblue];\n"));
+    assert!(dot_new_ast.contains("  n3 [label=\"Identifier\\nadd\", style=filled, fillcolor=lightblue];\n"));
+    assert!(dot_new_ast.contains("  n4 [label=\"NumberLiteral\\n2\", style=filled, fillcolor=lightblue];\n"));
+    assert!(dot_new_ast.contains("  n5 [label=\"StringLiteral\\nhoge\"];\n"));
+}
+
+#[test]
+fn test_constant_folding() {
+    assert_eq!(compile(&["-O1"], CODE), "'4'\nfullName('hoge','foo')\n");
+    assert_eq!(compile(&["-O1"], "(print (divide 7 2))"), "print(divide('7','2'))\n");
+}
+
+#[test]
+fn test_eval() {
+    let output = compile(&["--emit", "eval"], "(print (add 2 (subtract 4 2)))\n(print (concat 'hoge' 'foo') (lt 1 2))");
+
+    assert_eq!(output, "4\nhogefoo true\n");
+    assert_eq!(compile(&["--emit", "vm"], "(print (add 2 (subtract 4 2)))\n(print (concat 'hoge' 'foo') (lt 1 2))"), output);
+}
+
+#[test]
+fn test_vm_fails_where_eval_does() {
+    let run = |emit: &str| {
+        let output = run_compiler(&["--emit", emit], "(print 1) (add 99999999999999999999 1)");
+        (output.status.code(), String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+    };
+
+    let (status, stdout, stderr) = run("eval");
+    assert_eq!(status, Some(1));
+    assert!(stderr.contains("Error: Invalid number: 99999999999999999999 at 1:16"), "{}", stderr);
+    assert_eq!(run("vm"), (status, stdout, stderr));
+}
+
+#[test]
+fn test_emit_bytecode() {
+    let output = compile(&["--emit", "bytecode"], "(print (add 2 3))");
+
+    assert_eq!(output, "0000 PUSH_CONST 0 ; 2\n0001 PUSH_CONST 1 ; 3\n0002 CALL add 2\n0003 CALL print 1\n0004 YIELD\n0005 RET\n");
+}
+
+#[test]
+fn test_repl() {
+    let history = std::env::temp_dir().join(format!("super-tiny-cli-history-{}", std::process::id()));
+    let history = history.to_str().unwrap();
+
+    let output = compile(&["repl", "--history", history], "(add 2\n  (subtract 4 2))\n:mode js\n(fullName 'hoge' 'foo')\n");
+
+    assert_eq!(output, "> . 4\n> mode: js\n> fullName('hoge','foo')\n> \n");
+    assert_eq!(std::fs::read_to_string(history).unwrap(), "(add 2 (subtract 4 2))\n(fullName 'hoge' 'foo')\n");
+    std::fs::remove_file(history).unwrap();
+}
+
+#[test]
+fn test_repl_keeps_macros_in_every_mode() {
+    let history = std::env::temp_dir().join(format!("super-tiny-cli-macro-history-{}", std::process::id()));
+    let history = history.to_str().unwrap();
+
+    let output = compile(&["repl", "--history", history], "(defmacro double (x) `(add ,x ,x))\n(double 2)\n:mode js\n(double 21)\n");
+
+    assert_eq!(output, "> > 4\n> mode: js\n> add('21','21')\n> \n");
+    std::fs::remove_file(history).unwrap();
+}
+
+/// Compiles each `tests/golden/<dir>/*.lisp` with `args` and compares the output with the file
+/// of the same name and `extension`. Set `UPDATE_GOLDEN=1` to rewrite the expected files.
+fn assert_golden(dir: &str, extension: &str, args: &[&str]) {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(dir);
+    let mut sources = std::fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "lisp"))
+        .collect::<Vec<std::path::PathBuf>>();
+    sources.sort();
+    assert!(!sources.is_empty(), "no golden sources in {}", dir.display());
+
+    for source in sources {
+        let output = compile(args, &std::fs::read_to_string(&source).unwrap());
+        let expected_path = source.with_extension(extension);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&expected_path, &output).unwrap();
+        }
+
+        assert_eq!(output, std::fs::read_to_string(&expected_path).unwrap(), "{}", source.display());
+    }
+}
+
+#[test]
+fn test_js_golden() {
+    assert_golden("js", "js", &["--target", "js"]);
+}
+
+#[test]
+fn test_wat_golden() {
+    assert_golden("wat", "wat", &["--target", "wat"]);
+}
+
+#[test]
+fn test_c_golden() {
+    assert_golden("c", "c", &["--target", "c"]);
+}
+
+/// Generates code for each `tests/golden/<dir>/*.lisp` with `--target <dir>`, runs it with `run`
+/// and checks it prints what the evaluator prints. Where the evaluator fails, the code must fail
+/// with the same message, less its position. Skipped when `tool` is not installed.
+fn assert_runs_like_eval(dir: &str, extension: &str, tool: &str, run: impl Fn(&std::path::Path, &std::path::Path) -> std::process::Output) {
+    if Command::new(tool).arg("--version").output().is_err() {
+        eprintln!("skipping: no {}", tool);
+        return;
+    }
+
+    let sources = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(dir);
+    let build_dir = std::env::temp_dir().join(format!("super-tiny-{}-{}", dir, std::process::id()));
+    std::fs::create_dir_all(&build_dir).unwrap();
+
+    for entry in std::fs::read_dir(&sources).unwrap() {
+        let source = entry.unwrap().path();
+        if source.extension().map_or(true, |ext| ext != "lisp") {
+            continue;
+        }
+        let code = std::fs::read_to_string(&source).unwrap();
+        let generated = build_dir.join(source.with_extension(extension).file_name().unwrap());
+        std::fs::write(&generated, compile(&["--target", dir], &code)).unwrap();
+
+        let output = run(&generated, &build_dir);
+        let expected = run_compiler(&["--emit", "eval"], &code);
+
+        if expected.status.success() {
+            assert!(output.status.success(), "{} failed for {}: {}", tool, source.display(), String::from_utf8_lossy(&output.stderr));
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), String::from_utf8(expected.stdout).unwrap(), "{}", source.display());
+        } else {
+            let expected_error = String::from_utf8(expected.stderr).unwrap();
+            let (expected_message, _position) = expected_error.trim().trim_start_matches("Error: ").rsplit_once(" at ").unwrap();
+            let error = String::from_utf8(output.stderr).unwrap();
+            assert!(!output.status.success(), "{} succeeded for {}", tool, source.display());
+            assert_eq!(error.trim().lines().last().unwrap_or_default().trim_start_matches("Error: "), expected_message, "{}", source.display());
+        }
+    }
+    std::fs::remove_dir_all(build_dir).unwrap();
+}
+
+#[test]
+fn test_c_runs_like_eval() {
+    assert_runs_like_eval("c", "c", "cc", |generated, build_dir| {
+        let exe = build_dir.join(generated.file_stem().unwrap());
+        let status = Command::new("cc").args(["-std=c99", "-pedantic-errors", "-o"]).arg(&exe).arg(generated).status().unwrap();
+        assert!(status.success(), "cc failed for {}", generated.display());
+
+        Command::new(exe).output().unwrap()
+    });
+}
+
+#[test]
+fn test_python_golden() {
+    assert_golden("python", "py", &["--target", "python"]);
+}
+
+#[test]
+fn test_python_runs_like_eval() {
+    assert_runs_like_eval("python", "py", "python3", |generated, _| Command::new("python3").arg(generated).output().unwrap());
+}
+
+/// Type errors stop code generation from source, so these go through `--from new-ast`.
+#[cfg(feature = "serde")]
+#[test]
+fn test_python_type_errors_like_eval() {
+    if Command::new("python3").arg("--version").output().is_err() {
+        eprintln!("skipping: no python3");
+        return;
+    }
+
+    let cases = [
+        ("(print (add 'a' 'b'))", "add expects a number but got a string"),
+        ("(print (lt 'a' 1))", "lt expects a number but got a string"),
+        ("(print (concat 'a' 1))", "concat expects a string but got a number"),
+        ("(print (if 1 'then' 'else'))", "if expects a bool but got a number"),
+    ];
+    for (code, message) in cases {
+        assert!(compile_error(&["--emit", "eval"], code).contains(&format!("Error: {} at ", message)), "{}", code);
+
+        let python = compile(&["--from", "new-ast", "--target", "python"], &compile(&["--emit", "new-ast"], code));
+        let mut child = Command::new("python3").stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(python.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        assert_eq!(output.status.code(), Some(1), "{}", code);
+        assert_eq!(String::from_utf8(output.stderr).unwrap(), format!("Error: {}\n", message), "{}", code);
+    }
+}
+
+#[test]
+fn test_emit_source_map() {
+    let source_map = compile(&["--emit", "source-map"], "(add 2\n  (subtract 4 'x'))");
+
+    assert_eq!(source_map, concat!(
+        "{\"version\":3,\"sources\":[\"<stdin>\"],\"sourcesContent\":[\"(add 2\\n  (subtract 4 'x'))\"],",
+        "\"names\":[\"add\",\"subtract\"],\"mappings\":\"AAACA,GAAD,CAAK,IACFC,QAAD,CAAU,IAAE\"}\n",
+    ));
+}
+
+#[test]
+fn test_compile_pretty() {
+    let code = compile(&["--format", "pretty"], "(add 2 (subtract 4 2)) (concat 'hoge' 'foo')");
+
+    assert_eq!(code, "add('2', subtract('4', '2'));\n\nconcat('hoge', 'foo');\n");
+}
+
+#[test]
+fn test_emit_types() {
+    let types = compile(&["--emit", "types"], "(add 2 (subtract 4 2)) (if (lt 1 2) 'yes' 'no') (fullName 'hoge')");
+
+    assert_eq!(types, "Number\nString\nT0\n");
+}
+
+#[test]
+fn test_compile_rejects_type_mismatch() {
+    let error = compile_error(&["--target", "python"], "(add 1 'two')");
+
+    assert!(error.contains("Error: Type mismatch in argument 2 of add: expected Number but found String at 1:8"), "{}", error);
+}
+
+#[test]
+fn test_compile_rejects_arity_mismatch() {
+    let error = compile_error(&["--emit", "new-ast"], "(print (subtract 1 2 3))");
+
+    assert!(error.contains("Error: subtract expects 2 arguments but got 3 at 1:9"), "{}", error);
+}
+
+#[test]
+fn test_compile_strict() {
+    assert_eq!(compile(&["--strict"], "(add 1 2)"), "add('1','2')\n");
+
+    let error = compile_error(&["--strict"], "(add 1 (fullName 'hoge'))");
+
+    assert!(error.contains("Error: Unknown function: fullName at 1:9"), "{}", error);
+}
+
+#[test]
+fn test_unknown_function_suggestions() {
+    let eval_error = compile_error(&["--emit", "vm"], "(print (substract 4 2))");
+    let strict_error = compile_error(&["--strict"], "(ad 1 2)");
+
+    assert!(eval_error.contains("Error: Unknown function: substract at 1:9 (did you mean subtract?)"), "{}", eval_error);
+    assert!(strict_error.contains("Error: Unknown function: ad at 1:2 (did you mean add?)"), "{}", strict_error);
+}
+
+#[test]
+fn test_emit_expanded() {
+    let code = "(defmacro unless (condition then otherwise) `(if ,condition ,otherwise ,then))\n(print (unless (gt 1 2) 'yes' 'no'))";
+
+    assert_eq!(compile(&["--emit", "expanded"], code), "(print (if (gt 1 2) 'no' 'yes'))\n");
+    assert_eq!(compile(&["--emit", "eval"], code), "yes\n");
+    assert_eq!(compile(&[], code), "print((gt('1','2') ? 'no' : 'yes'))\n");
+}
+
+#[test]
+fn test_macro_expansion_depth_limit() {
+    let error = compile_error(&["--emit", "expanded"], "(defmacro forever (x) `(forever ,x))\n(forever 1)");
+
+    assert!(error.contains("Error: Expansion of forever is more than 100 macro calls deep at 2:2"), "{}", error);
+}
+
+#[test]
+fn test_macro_resolution_errors() {
+    let unbound = compile_error(&["--emit", "expanded"], "(defmacro m (x) `(print ,y))");
+    let duplicate = compile_error(&["--emit", "expanded"], "(defmacro m (x x) x)");
+
+    assert!(unbound.contains("Error: Unbound symbol: y at 1:26"), "{}", unbound);
+    assert!(duplicate.contains("Error: x is already defined at 1:14, again at 1:16"), "{}", duplicate);
+}
+
+/// Writes `files` into a new directory named after `test`, and returns it.
+fn write_modules(test: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("super-tiny-cli-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (name, code) in files {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, code).unwrap();
+    }
+    dir
+}
+
+const MODULES: [(&str, &str); 2] = [
+    ("main.lisp", "(import \"lib/math.lisp\")\n(print (double 21))"),
+    ("lib/math.lisp", "(defmacro double (x) `(multiply 2 ,x))\n(export double)\n(print 'math loaded')"),
+];
+
+#[test]
+fn test_compile_modules_bundled() {
+    let dir = write_modules("bundle", &MODULES);
+    let main = dir.join("main.lisp");
+
+    assert_eq!(compile(&[main.to_str().unwrap()], ""), "print('math loaded')\nprint(multiply('2','21'))\n");
+    assert_eq!(compile(&["--emit", "eval", main.to_str().unwrap()], ""), "math loaded\n42\n");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_compile_modules_to_out_dir() {
+    let dir = write_modules("out-dir", &MODULES);
+    let out = dir.join("out");
+
+    let written = compile(&["--target", "python", "--out-dir", out.to_str().unwrap(), dir.join("main.lisp").to_str().unwrap()], "");
+
+    assert_eq!(written, format!("{}\n{}\n", out.join("lib/math.py").display(), out.join("main.py").display()));
+    assert_eq!(std::fs::read_to_string(out.join("lib/math.py")).unwrap(), compile(&["--target", "python"], "(print 'math loaded')"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_import_cycle() {
+    let dir = write_modules("cycle", &[("a.lisp", "(import \"b.lisp\")"), ("b.lisp", "(import \"a.lisp\")")]);
+
+    let error = compile_error(&[dir.join("a.lisp").to_str().unwrap()], "");
+
+    assert!(error.contains("Error: Import cycle: a.lisp -> b.lisp -> a.lisp"), "{}", error);
+    std::fs::remove_dir_all(dir).unwrap();
+}
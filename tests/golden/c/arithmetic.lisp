@@ -0,0 +1,3 @@
+(print (add 2 (subtract 4 2)))
+(print (concat 'hoge' 'foo') (multiply 6 7))
+(print (eq (print 'first') (print 'second')))
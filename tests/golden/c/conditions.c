@@ -0,0 +1,180 @@
+/* Runtime for C generated by the-super-tiny-compiler-rust. Builtins behave like the evaluator's. */
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef enum { TINY_NIL, TINY_NUMBER, TINY_STRING, TINY_BOOL } tiny_tag;
+
+typedef struct {
+    tiny_tag tag;
+    union {
+        long long number;
+        const char *string;
+        int boolean;
+    } as;
+} value;
+
+static void tiny_fail(const char *name, const char *message) {
+    fprintf(stderr, "Error: %s in %s\n", message, name);
+    exit(1);
+}
+
+static value tiny_nil(void) { value v; v.tag = TINY_NIL; v.as.number = 0; return v; }
+static value tiny_number(long long number) { value v; v.tag = TINY_NUMBER; v.as.number = number; return v; }
+static value tiny_string(const char *string) { value v; v.tag = TINY_STRING; v.as.string = string; return v; }
+static value tiny_bool(int boolean) { value v; v.tag = TINY_BOOL; v.as.boolean = boolean != 0; return v; }
+
+static void tiny_expect(const char *name, int argc, int expected) {
+    if (argc != expected) {
+        fprintf(stderr, "Error: %s expects %d arguments but got %d\n", name, expected, argc);
+        exit(1);
+    }
+}
+
+static long long tiny_expect_number(const char *name, value v) {
+    if (v.tag != TINY_NUMBER) {
+        tiny_fail(name, "Expected a number");
+    }
+    return v.as.number;
+}
+
+static int tiny_truthy(value v) {
+    if (v.tag != TINY_BOOL) {
+        tiny_fail("if", "Expected a bool");
+    }
+    return v.as.boolean;
+}
+
+static value tiny_add(int argc, const value *argv) {
+    long long a, b;
+    tiny_expect("add", argc, 2);
+    a = tiny_expect_number("add", argv[0]);
+    b = tiny_expect_number("add", argv[1]);
+    if ((b > 0 && a > LLONG_MAX - b) || (b < 0 && a < LLONG_MIN - b)) {
+        tiny_fail("add", "Integer overflow");
+    }
+    return tiny_number(a + b);
+}
+
+static value tiny_subtract(int argc, const value *argv) {
+    long long a, b;
+    tiny_expect("subtract", argc, 2);
+    a = tiny_expect_number("subtract", argv[0]);
+    b = tiny_expect_number("subtract", argv[1]);
+    if ((b < 0 && a > LLONG_MAX + b) || (b > 0 && a < LLONG_MIN + b)) {
+        tiny_fail("subtract", "Integer overflow");
+    }
+    return tiny_number(a - b);
+}
+
+static value tiny_multiply(int argc, const value *argv) {
+    long long a, b;
+    tiny_expect("multiply", argc, 2);
+    a = tiny_expect_number("multiply", argv[0]);
+    b = tiny_expect_number("multiply", argv[1]);
+    /* Checked before multiplying, as signed overflow is undefined */
+    if (a > 0 ? (b > 0 ? a > LLONG_MAX / b : b < LLONG_MIN / a)
+              : (b > 0 ? a < LLONG_MIN / b : a != 0 && b < LLONG_MAX / a)) {
+        tiny_fail("multiply", "Integer overflow");
+    }
+    return tiny_number(a * b);
+}
+
+static value tiny_divide(int argc, const value *argv) {
+    long long a, b;
+    tiny_expect("divide", argc, 2);
+    a = tiny_expect_number("divide", argv[0]);
+    b = tiny_expect_number("divide", argv[1]);
+    if (b == 0) {
+        tiny_fail("divide", "Division by zero");
+    }
+    if (a == LLONG_MIN && b == -1) {
+        tiny_fail("divide", "Integer overflow");
+    }
+    return tiny_number(a / b);
+}
+
+static value tiny_concat(int argc, const value *argv) {
+    size_t length = 0;
+    char *result;
+    int i;
+    for (i = 0; i < argc; i++) {
+        if (argv[i].tag != TINY_STRING) {
+            tiny_fail("concat", "Expected a string");
+        }
+        length += strlen(argv[i].as.string);
+    }
+    result = malloc(length + 1);
+    if (result == NULL) {
+        tiny_fail("concat", "Out of memory");
+    }
+    result[0] = '\0';
+    for (i = 0; i < argc; i++) {
+        strcat(result, argv[i].as.string);
+    }
+    return tiny_string(result);
+}
+
+static void tiny_write(value v) {
+    switch (v.tag) {
+    case TINY_NIL: fputs("nil", stdout); break;
+    case TINY_NUMBER: printf("%lld", v.as.number); break;
+    case TINY_STRING: fputs(v.as.string, stdout); break;
+    case TINY_BOOL: fputs(v.as.boolean ? "true" : "false", stdout); break;
+    }
+}
+
+static value tiny_print(int argc, const value *argv) {
+    int i;
+    for (i = 0; i < argc; i++) {
+        if (i > 0) {
+            putchar(' ');
+        }
+        tiny_write(argv[i]);
+    }
+    putchar('\n');
+    return tiny_nil();
+}
+
+static value tiny_eq(int argc, const value *argv) {
+    tiny_expect("eq", argc, 2);
+    if (argv[0].tag != argv[1].tag) {
+        return tiny_bool(0);
+    }
+    switch (argv[0].tag) {
+    case TINY_NIL: return tiny_bool(1);
+    case TINY_NUMBER: return tiny_bool(argv[0].as.number == argv[1].as.number);
+    case TINY_STRING: return tiny_bool(strcmp(argv[0].as.string, argv[1].as.string) == 0);
+    case TINY_BOOL: return tiny_bool(argv[0].as.boolean == argv[1].as.boolean);
+    }
+    return tiny_bool(0);
+}
+
+#define TINY_COMPARISON(name, op) \
+    static value tiny_##name(int argc, const value *argv) { \
+        tiny_expect(#name, argc, 2); \
+        return tiny_bool(tiny_expect_number(#name, argv[0]) op tiny_expect_number(#name, argv[1])); \
+    }
+
+TINY_COMPARISON(lt, <)
+TINY_COMPARISON(le, <=)
+TINY_COMPARISON(gt, >)
+TINY_COMPARISON(ge, >=)
+
+int main(void) {
+    value t0 = tiny_lt(2, (value[]){tiny_number(1LL), tiny_number(2LL)});
+    value t1;
+    if (tiny_truthy(t0)) {
+        t1 = tiny_string("yes");
+    } else {
+        t1 = tiny_string("no");
+    }
+    value t2 = tiny_eq(2, (value[]){tiny_string("a"), tiny_string("a")});
+    value t3 = tiny_ge(2, (value[]){tiny_number(1LL), tiny_number(2LL)});
+    value t4 = tiny_print(3, (value[]){t1, t2, t3});
+    (void)t4;
+    value t5 = tiny_print(1, (value[]){tiny_string("why\?\?= caf\303\251")});
+    (void)t5;
+    return 0;
+}
@@ -0,0 +1,2 @@
+(print 'before')
+(print (multiply 9223372036854775807 2))
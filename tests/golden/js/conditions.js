@@ -0,0 +1,2 @@
+print((lt('1','2') ? 'yes' : 'no'),eq('a','a'))
+print((eq('a','b') ? add('1','2') : (gt('2','1') ? '3' : '4')))
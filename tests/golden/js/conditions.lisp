@@ -0,0 +1,2 @@
+(print (if (lt 1 2) 'yes' 'no') (eq 'a' 'a'))
+(print (if (eq 'a' 'b') (add 1 2) (if (gt 2 1) 3 4)))
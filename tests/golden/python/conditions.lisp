@@ -0,0 +1,2 @@
+(print (if (lt 1 2) 'yes' 'no') (eq 'a' 'a') (ge 1 2))
+(print 'why??= café')
@@ -0,0 +1 @@
+(print (divide 7 2) (divide (subtract 0 7) 2) (eq 1 'a') (print))
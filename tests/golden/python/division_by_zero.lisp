@@ -0,0 +1 @@
+(print (divide 1 (subtract 2 2)))
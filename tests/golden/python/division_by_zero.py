@@ -0,0 +1,112 @@
+import builtins as _builtins
+import sys as _sys
+
+_MIN, _MAX = -2 ** 63, 2 ** 63 - 1
+
+
+def _fail(message):
+    _sys.stderr.write('Error: ' + message + '\n')
+    _sys.exit(1)
+
+
+def _type_name(value):
+    if value is None:
+        return 'nil'
+    if isinstance(value, bool):
+        return 'bool'
+    return 'number' if isinstance(value, int) else 'string'
+
+
+def _expect(name, args, count):
+    if len(args) != count:
+        _fail('%s expects %d arguments but got %d' % (name, count, len(args)))
+    return args
+
+
+def _numbers(name, args):
+    for arg in _expect(name, args, 2):
+        if _type_name(arg) != 'number':
+            _fail('%s expects a number but got a %s' % (name, _type_name(arg)))
+    return args
+
+
+def _checked(name, number):
+    if not _MIN <= number <= _MAX:
+        _fail('Integer overflow in ' + name)
+    return number
+
+
+def _truthy(value):
+    if _type_name(value) != 'bool':
+        _fail('if expects a bool but got a ' + _type_name(value))
+    return value
+
+
+def add(*args):
+    a, b = _numbers('add', args)
+    return _checked('add', a + b)
+
+
+def subtract(*args):
+    a, b = _numbers('subtract', args)
+    return _checked('subtract', a - b)
+
+
+def multiply(*args):
+    a, b = _numbers('multiply', args)
+    return _checked('multiply', a * b)
+
+
+def divide(*args):
+    a, b = _numbers('divide', args)
+    if b == 0:
+        _fail('Division by zero')
+    quotient = abs(a) // abs(b)
+    return _checked('divide', quotient if (a >= 0) == (b >= 0) else -quotient)
+
+
+def concat(*args):
+    for arg in args:
+        if _type_name(arg) != 'string':
+            _fail('concat expects a string but got a ' + _type_name(arg))
+    return ''.join(args)
+
+
+def eq(*args):
+    a, b = _expect('eq', args, 2)
+    return type(a) is type(b) and a == b
+
+
+def lt(*args):
+    a, b = _numbers('lt', args)
+    return a < b
+
+
+def le(*args):
+    a, b = _numbers('le', args)
+    return a <= b
+
+
+def gt(*args):
+    a, b = _numbers('gt', args)
+    return a > b
+
+
+def ge(*args):
+    a, b = _numbers('ge', args)
+    return a >= b
+
+
+def _show(value):
+    if value is None:
+        return 'nil'
+    if isinstance(value, bool):
+        return 'true' if value else 'false'
+    return str(value)
+
+
+def print(*args):
+    _builtins.print(*map(_show, args))
+
+
+print(divide(1, subtract(2, 2)))
@@ -0,0 +1 @@
+(add 2 (subtract 4 2))
@@ -0,0 +1,5 @@
+(module
+  (func $main (export "main") (result i64)
+    (i64.add (i64.const 2) (i64.sub (i64.const 4) (i64.const 2)))
+  )
+)
@@ -0,0 +1,5 @@
+(module
+  (func $main (export "main") (result i64)
+    (i64.const 0)
+  )
+)
@@ -0,0 +1,2 @@
+(multiply 6 7)
+(divide (add 10 (multiply 2 5)) (subtract 9 4))
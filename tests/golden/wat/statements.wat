@@ -0,0 +1,6 @@
+(module
+  (func $main (export "main") (result i64)
+    (drop (i64.mul (i64.const 6) (i64.const 7)))
+    (i64.div_s (i64.add (i64.const 10) (i64.mul (i64.const 2) (i64.const 5))) (i64.sub (i64.const 9) (i64.const 4)))
+  )
+)